@@ -0,0 +1,6 @@
+//Analyses of whole programs and of single functions, shared by semantic checks, optimizations and code generation.
+
+pub mod callgraph;
+pub mod cfg;
+pub mod dataflow;
+pub mod dominators;
@@ -0,0 +1,140 @@
+use crate::ir::{Inst, Module};
+use std::collections::{HashMap, HashSet};
+
+/// Which functions call which other functions.
+/// Only user-defined functions appear in the graph; calls to builtins are ignored.
+pub struct CallGraph {
+	/// Every function, in the order it was declared.
+	pub functions: Vec<String>,
+	calls: HashMap<String, Vec<String>>,
+}
+
+impl CallGraph {
+	pub fn build(module: &Module) -> CallGraph {
+		let mut graph = CallGraph {
+			functions: vec![],
+			calls: HashMap::new(),
+		};
+
+		for function in &module.functions {
+			graph.functions.push(function.name.clone());
+			let mut callees: Vec<String> = vec![];
+			for block in &function.blocks {
+				for inst in &block.insts {
+					if let Inst::Call(_, name, _) = inst {
+						if !callees.contains(name) {
+							callees.push(name.clone());
+						}
+					}
+				}
+			}
+			graph.calls.insert(function.name.clone(), callees);
+		}
+
+		//Drop any calls to builtins.
+		let declared: HashSet<String> = graph.functions.iter().cloned().collect();
+		for callees in graph.calls.values_mut() {
+			callees.retain(|callee| declared.contains(callee));
+		}
+
+		graph
+	}
+
+	/// Functions that are called directly by the given function, each listed once.
+	pub fn callees(&self, name: &str) -> &[String] {
+		match self.calls.get(name) {
+			Some(callees) => callees,
+			None => &[],
+		}
+	}
+
+	/// Functions that can be called, directly or indirectly, from any of the given functions.
+	/// The given functions are included.
+	pub fn reachable(&self, roots: &[&str]) -> HashSet<String> {
+		let mut reachable: HashSet<String> = HashSet::new();
+		let mut stack: Vec<&str> = roots.to_vec();
+		while let Some(name) = stack.pop() {
+			if reachable.insert(name.to_string()) {
+				stack.extend(self.callees(name).iter().map(|callee| callee.as_str()));
+			}
+		}
+		reachable
+	}
+
+	/// Group functions into strongly connected components: sets of functions that can all
+	/// (indirectly) call each other. Components are listed with callees before their callers.
+	pub fn components(&self) -> Vec<Vec<String>> {
+		//Tarjan's algorithm.
+		struct State<'g> {
+			graph: &'g CallGraph,
+			index: HashMap<&'g str, usize>,
+			lowlink: HashMap<&'g str, usize>,
+			stack: Vec<&'g str>,
+			on_stack: HashSet<&'g str>,
+			components: Vec<Vec<String>>,
+		}
+
+		fn visit<'g>(state: &mut State<'g>, name: &'g str) {
+			let index = state.index.len();
+			state.index.insert(name, index);
+			state.lowlink.insert(name, index);
+			state.stack.push(name);
+			state.on_stack.insert(name);
+
+			for callee in state.graph.callees(name) {
+				let callee = callee.as_str();
+				if !state.index.contains_key(callee) {
+					visit(state, callee);
+					let low = state.lowlink[name].min(state.lowlink[callee]);
+					state.lowlink.insert(name, low);
+				} else if state.on_stack.contains(callee) {
+					let low = state.lowlink[name].min(state.index[callee]);
+					state.lowlink.insert(name, low);
+				}
+			}
+
+			if state.lowlink[name] == index {
+				let mut component = vec![];
+				loop {
+					let member = state.stack.pop().unwrap();
+					state.on_stack.remove(member);
+					component.push(member.to_string());
+					if member == name {
+						break;
+					}
+				}
+				state.components.push(component);
+			}
+		}
+
+		let mut state = State {
+			graph: self,
+			index: HashMap::new(),
+			lowlink: HashMap::new(),
+			stack: vec![],
+			on_stack: HashSet::new(),
+			components: vec![],
+		};
+
+		for name in &self.functions {
+			if !state.index.contains_key(name.as_str()) {
+				visit(&mut state, name);
+			}
+		}
+
+		state.components
+	}
+
+	/// Functions that can end up calling themselves, directly or through other functions.
+	/// These can have more than one activation live at once.
+	pub fn recursive(&self) -> HashSet<String> {
+		let mut result = HashSet::new();
+		for component in self.components() {
+			let name = &component[0];
+			if component.len() > 1 || self.callees(name).contains(name) {
+				result.extend(component);
+			}
+		}
+		result
+	}
+}
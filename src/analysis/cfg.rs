@@ -0,0 +1,80 @@
+use crate::ir::{BlockId, Function, Operand, Terminator};
+
+/// The control-flow graph of a function: which blocks can run right after which.
+/// Block 0 is always the entry.
+pub struct Cfg {
+	pub successors: Vec<Vec<BlockId>>,
+	pub predecessors: Vec<Vec<BlockId>>,
+}
+
+impl Cfg {
+	pub fn build(function: &Function) -> Cfg {
+		let mut cfg = Cfg {
+			successors: vec![vec![]; function.blocks.len()],
+			predecessors: vec![vec![]; function.blocks.len()],
+		};
+
+		for (index, block) in function.blocks.iter().enumerate() {
+			let successors = match block.terminator {
+				//A branch on two constants only ever goes one way, e.g. `while 1 { ... }`.
+				Terminator::Branch(comparison, Operand::Const(a), Operand::Const(b), yes, no) => {
+					if comparison.holds(a, b) {
+						vec![yes]
+					} else {
+						vec![no]
+					}
+				}
+				_ => block.terminator.successors(),
+			};
+
+			for successor in successors {
+				if !cfg.successors[index].contains(&successor) {
+					cfg.successors[index].push(successor);
+					cfg.predecessors[successor.0].push(BlockId(index));
+				}
+			}
+		}
+
+		cfg
+	}
+
+	/// How many blocks there are.
+	pub fn len(&self) -> usize {
+		self.successors.len()
+	}
+
+	/// Which blocks can be reached from the entry.
+	pub fn reachable(&self) -> Vec<bool> {
+		let mut reachable = vec![false; self.len()];
+		for block in self.reverse_postorder() {
+			reachable[block.0] = true;
+		}
+		reachable
+	}
+
+	/// Every block reachable from the entry, ordered so that each block comes before its
+	/// successors, except along edges that go back to the start of a loop.
+	pub fn reverse_postorder(&self) -> Vec<BlockId> {
+		let mut visited = vec![false; self.len()];
+		let mut order = vec![];
+
+		//Depth-first, keeping track of how many successors of each block have been visited so far.
+		let mut stack = vec![(BlockId(0), 0)];
+		visited[0] = true;
+		while let Some((block, next)) = stack.pop() {
+			match self.successors[block.0].get(next) {
+				Some(&successor) => {
+					stack.push((block, next + 1));
+					if !visited[successor.0] {
+						visited[successor.0] = true;
+						stack.push((successor, 0));
+					}
+				}
+				None => order.push(block),
+			}
+		}
+
+		order.reverse();
+		order
+	}
+}
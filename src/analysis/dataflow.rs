@@ -0,0 +1,166 @@
+use super::cfg::Cfg;
+use crate::ir::{BlockId, Function};
+use std::collections::VecDeque;
+
+pub mod liveness;
+pub mod reaching;
+
+/// Which way facts flow through the control-flow graph.
+pub enum Direction {
+	/// From the entry towards the returns, e.g. which definitions might have happened.
+	Forward,
+	/// From the returns back towards the entry, e.g. which values might still be needed.
+	Backward,
+}
+
+/// A dataflow problem over the blocks of a function.
+/// Facts form a lattice; `meet` combines the facts where paths join, and `initial` should be
+/// the fact that doesn't change anything it's combined with.
+pub trait Analysis {
+	type Fact: Clone + PartialEq;
+	const DIRECTION: Direction;
+
+	/// The fact at the entry (going forward), or at every return (going backward).
+	fn boundary(&self, function: &Function) -> Self::Fact;
+
+	/// The fact every other block starts out with.
+	fn initial(&self, function: &Function) -> Self::Fact;
+
+	fn meet(&self, fact: &mut Self::Fact, other: &Self::Fact);
+
+	/// Apply the effect of a whole block. Going forward the fact is what holds at the top of
+	/// the block and should become what holds at the bottom, and going backward it's the reverse.
+	fn transfer(&self, function: &Function, block: BlockId, fact: &mut Self::Fact);
+}
+
+/// What holds at the top (`entry`) and bottom (`exit`) of every block.
+pub struct Results<F> {
+	pub entry: Vec<F>,
+	pub exit: Vec<F>,
+}
+
+/// Find the fixed point of a dataflow problem.
+/// Unreachable blocks are included, since their code still exists until something removes it.
+pub fn solve<A: Analysis>(analysis: &A, function: &Function, cfg: &Cfg) -> Results<A::Fact> {
+	let initial = analysis.initial(function);
+	let boundary = analysis.boundary(function);
+	let mut results = Results {
+		entry: vec![initial.clone(); cfg.len()],
+		exit: vec![initial.clone(); cfg.len()],
+	};
+
+	//Start from the order that's likely to need the fewest passes.
+	let mut order = cfg.reverse_postorder();
+	for index in 0..cfg.len() {
+		if !order.contains(&BlockId(index)) {
+			order.push(BlockId(index));
+		}
+	}
+	if let Direction::Backward = A::DIRECTION {
+		order.reverse();
+	}
+
+	let mut queued = vec![true; cfg.len()];
+	let mut worklist: VecDeque<BlockId> = order.into_iter().collect();
+
+	while let Some(block) = worklist.pop_front() {
+		queued[block.0] = false;
+
+		//Facts flow in from these blocks, and out to the others.
+		let (sources, targets) = match A::DIRECTION {
+			Direction::Forward => (&cfg.predecessors[block.0], &cfg.successors[block.0]),
+			Direction::Backward => (&cfg.successors[block.0], &cfg.predecessors[block.0]),
+		};
+		let at_boundary = match A::DIRECTION {
+			Direction::Forward => block.0 == 0,
+			Direction::Backward => sources.is_empty(),
+		};
+
+		let mut fact = if at_boundary {
+			boundary.clone()
+		} else {
+			initial.clone()
+		};
+		for source in sources {
+			let other = match A::DIRECTION {
+				Direction::Forward => &results.exit[source.0],
+				Direction::Backward => &results.entry[source.0],
+			};
+			analysis.meet(&mut fact, other);
+		}
+
+		let mut out = fact.clone();
+		analysis.transfer(function, block, &mut out);
+
+		let (input, output) = match A::DIRECTION {
+			Direction::Forward => (&mut results.entry[block.0], &mut results.exit[block.0]),
+			Direction::Backward => (&mut results.exit[block.0], &mut results.entry[block.0]),
+		};
+		*input = fact;
+		if *output != out {
+			*output = out;
+			for target in targets {
+				if !queued[target.0] {
+					queued[target.0] = true;
+					worklist.push_back(*target);
+				}
+			}
+		}
+	}
+
+	results
+}
+
+/// A fixed-size set of small numbers, such as temporaries or blocks.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct BitSet {
+	len: usize,
+	words: Vec<u64>,
+}
+
+impl BitSet {
+	/// A set that can hold the numbers `0..len`, starting out empty.
+	pub fn new(len: usize) -> BitSet {
+		BitSet {
+			len,
+			words: vec![0; len.div_ceil(64)],
+		}
+	}
+
+	/// A set holding every number in `0..len`.
+	pub fn full(len: usize) -> BitSet {
+		let mut set = BitSet::new(len);
+		for item in 0..len {
+			set.insert(item);
+		}
+		set
+	}
+
+	pub fn insert(&mut self, item: usize) {
+		self.words[item / 64] |= 1 << (item % 64);
+	}
+
+	pub fn remove(&mut self, item: usize) {
+		self.words[item / 64] &= !(1 << (item % 64));
+	}
+
+	pub fn contains(&self, item: usize) -> bool {
+		self.words[item / 64] & (1 << (item % 64)) != 0
+	}
+
+	pub fn union_with(&mut self, other: &BitSet) {
+		for (word, other) in self.words.iter_mut().zip(&other.words) {
+			*word |= other;
+		}
+	}
+
+	pub fn intersect_with(&mut self, other: &BitSet) {
+		for (word, other) in self.words.iter_mut().zip(&other.words) {
+			*word &= other;
+		}
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+		(0..self.len).filter(|item| self.contains(*item))
+	}
+}
@@ -0,0 +1,48 @@
+use super::{Analysis, BitSet, Direction};
+use crate::ir::{BlockId, Function, Inst, Operand};
+
+/// Which temporaries might still be read later. A value that isn't live is never needed again,
+/// so its storage can be reused and anything that only computes it can go.
+pub struct Liveness;
+
+impl Analysis for Liveness {
+	type Fact = BitSet;
+	const DIRECTION: Direction = Direction::Backward;
+
+	fn boundary(&self, function: &Function) -> BitSet {
+		BitSet::new(function.temps.len())
+	}
+
+	fn initial(&self, function: &Function) -> BitSet {
+		BitSet::new(function.temps.len())
+	}
+
+	fn meet(&self, fact: &mut BitSet, other: &BitSet) {
+		fact.union_with(other);
+	}
+
+	fn transfer(&self, function: &Function, block: BlockId, live: &mut BitSet) {
+		let block = &function.blocks[block.0];
+		read(block.terminator.operands(), live);
+		for inst in block.insts.iter().rev() {
+			step(inst, live);
+		}
+	}
+}
+
+/// Move the set of live temporaries from just after an instruction to just before it.
+pub fn step(inst: &Inst, live: &mut BitSet) {
+	if let Some(dest) = inst.dest() {
+		live.remove(dest.0);
+	}
+	read(inst.operands(), live);
+}
+
+/// Mark the temporaries among some operands as live.
+pub fn read(operands: Vec<Operand>, live: &mut BitSet) {
+	for operand in operands {
+		if let Operand::Temp(temp) = operand {
+			live.insert(temp.0);
+		}
+	}
+}
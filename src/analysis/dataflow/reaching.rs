@@ -0,0 +1,104 @@
+use super::{Analysis, BitSet, Direction};
+use crate::ir::{BlockId, Function, Inst, Temp};
+
+/// Somewhere a temporary gets a value.
+pub enum Site {
+	/// Parameters get their values from the caller, before the function starts.
+	Param,
+	/// An instruction, given by its block and its position in that block.
+	Inst(BlockId, usize),
+}
+
+/// Which definitions might have given each temporary its current value.
+/// A definition reaches a point if there's a path from it to that point that doesn't
+/// assign to the same temporary again.
+pub struct ReachingDefinitions {
+	/// Every definition in the function. Facts are sets of indices into this.
+	pub definitions: Vec<Site>,
+	//Definitions of each temporary
+	by_temp: Vec<Vec<usize>>,
+	//Definition made by each instruction of each block, if any
+	by_inst: Vec<Vec<Option<usize>>>,
+}
+
+impl ReachingDefinitions {
+	pub fn new(function: &Function) -> ReachingDefinitions {
+		let mut reaching = ReachingDefinitions {
+			definitions: vec![],
+			by_temp: vec![vec![]; function.temps.len()],
+			by_inst: vec![],
+		};
+
+		for param in &function.params {
+			reaching.add(*param, Site::Param);
+		}
+
+		for (index, block) in function.blocks.iter().enumerate() {
+			let mut by_inst = vec![];
+			for (position, inst) in block.insts.iter().enumerate() {
+				by_inst.push(
+					inst.dest()
+						.map(|dest| reaching.add(dest, Site::Inst(BlockId(index), position))),
+				);
+			}
+			reaching.by_inst.push(by_inst);
+		}
+
+		reaching
+	}
+
+	fn add(&mut self, temp: Temp, site: Site) -> usize {
+		self.definitions.push(site);
+		self.by_temp[temp.0].push(self.definitions.len() - 1);
+		self.definitions.len() - 1
+	}
+
+	/// The definitions of a temporary that are in a set of reaching definitions.
+	pub fn of<'a>(&'a self, temp: Temp, fact: &'a BitSet) -> impl Iterator<Item = usize> + 'a {
+		self.by_temp[temp.0]
+			.iter()
+			.copied()
+			.filter(|definition| fact.contains(*definition))
+	}
+
+	/// Move the set of reaching definitions from just before an instruction to just after it.
+	pub fn step(&self, block: BlockId, position: usize, inst: &Inst, fact: &mut BitSet) {
+		if let Some(dest) = inst.dest() {
+			for definition in &self.by_temp[dest.0] {
+				fact.remove(*definition);
+			}
+		}
+		if let Some(definition) = self.by_inst[block.0][position] {
+			fact.insert(definition);
+		}
+	}
+}
+
+impl Analysis for ReachingDefinitions {
+	type Fact = BitSet;
+	const DIRECTION: Direction = Direction::Forward;
+
+	fn boundary(&self, _function: &Function) -> BitSet {
+		let mut fact = BitSet::new(self.definitions.len());
+		for (index, definition) in self.definitions.iter().enumerate() {
+			if let Site::Param = definition {
+				fact.insert(index);
+			}
+		}
+		fact
+	}
+
+	fn initial(&self, _function: &Function) -> BitSet {
+		BitSet::new(self.definitions.len())
+	}
+
+	fn meet(&self, fact: &mut BitSet, other: &BitSet) {
+		fact.union_with(other);
+	}
+
+	fn transfer(&self, function: &Function, block: BlockId, fact: &mut BitSet) {
+		for (position, inst) in function.blocks[block.0].insts.iter().enumerate() {
+			self.step(block, position, inst, fact);
+		}
+	}
+}
@@ -0,0 +1,83 @@
+use super::cfg::Cfg;
+use super::dataflow::{self, Analysis, BitSet, Direction};
+use crate::ir::{BlockId, Function};
+
+/// Block `a` dominates block `b` if every path from the entry to `b` goes through `a`.
+pub struct Dominators {
+	sets: Vec<BitSet>,
+	reachable: Vec<bool>,
+}
+
+//Each block's dominators are itself plus whatever dominates all of its predecessors.
+struct DominatorAnalysis;
+
+impl Analysis for DominatorAnalysis {
+	type Fact = BitSet;
+	const DIRECTION: Direction = Direction::Forward;
+
+	fn boundary(&self, function: &Function) -> BitSet {
+		BitSet::new(function.blocks.len())
+	}
+
+	fn initial(&self, function: &Function) -> BitSet {
+		BitSet::full(function.blocks.len())
+	}
+
+	fn meet(&self, fact: &mut BitSet, other: &BitSet) {
+		fact.intersect_with(other);
+	}
+
+	fn transfer(&self, _function: &Function, block: BlockId, fact: &mut BitSet) {
+		fact.insert(block.0);
+	}
+}
+
+impl Dominators {
+	pub fn build(function: &Function, cfg: &Cfg) -> Dominators {
+		Dominators {
+			sets: dataflow::solve(&DominatorAnalysis, function, cfg).exit,
+			reachable: cfg.reachable(),
+		}
+	}
+
+	/// Whether `a` dominates `b`. Every block dominates itself.
+	/// Nothing is said to dominate an unreachable block, since there are no paths to it.
+	pub fn dominates(&self, a: BlockId, b: BlockId) -> bool {
+		self.reachable[b.0] && self.sets[b.0].contains(a.0)
+	}
+}
+
+/// How many loops each block is inside of.
+/// A loop is found wherever a block jumps back to one that dominates it (the loop's head).
+/// Its body is everything that can reach that jump without going through the head.
+pub fn loop_depths(function: &Function, cfg: &Cfg) -> Vec<usize> {
+	let dominators = Dominators::build(function, cfg);
+	let mut bodies: Vec<Option<BitSet>> = vec![None; cfg.len()];
+
+	for (index, successors) in cfg.successors.iter().enumerate() {
+		for head in successors {
+			if !dominators.dominates(*head, BlockId(index)) {
+				continue;
+			}
+
+			//Several jumps back to the same head, like `continue`, are all the same loop.
+			let body = bodies[head.0].get_or_insert_with(|| BitSet::new(cfg.len()));
+			body.insert(head.0);
+			let mut stack = vec![BlockId(index)];
+			while let Some(block) = stack.pop() {
+				if !body.contains(block.0) {
+					body.insert(block.0);
+					stack.extend(&cfg.predecessors[block.0]);
+				}
+			}
+		}
+	}
+
+	let mut depths = vec![0; cfg.len()];
+	for body in bodies.iter().flatten() {
+		for block in body.iter() {
+			depths[block] += 1;
+		}
+	}
+	depths
+}
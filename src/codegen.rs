@@ -1,4 +1,416 @@
+use crate::analysis::callgraph::CallGraph;
+use crate::analysis::cfg::Cfg;
+use crate::analysis::dominators;
+use crate::flags::Options;
+use crate::ir;
+use crate::message;
+use crate::message::Context;
+use crate::target::{self, Target};
+use asm::Bytecode;
+use asm::Bytecode::*;
+use asm::Mode::*;
+use asm::Value;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
 pub mod asm;
-mod expression;
-mod program;
-mod statement;
+mod assembler;
+mod frame;
+mod function;
+mod memory;
+mod ops;
+pub mod peephole;
+mod registers;
+mod runtime;
+mod zeropage;
+
+use frame::{FrameUsage, MemoryMap};
+use memory::{Loc, Workspace, STACK_OVERFLOW, STACK_PTR};
+use registers::{Register, Tracker};
+use runtime::Routine;
+
+pub struct Generator<'a> {
+	context: &'a Context<'a>,
+	module: &'a ir::Module,
+	target: &'a Target,
+	code: Vec<Bytecode>,
+	runtime: BTreeSet<Routine>,
+	labels: usize,
+	//Functions that need a new frame for every call, because they can call themselves
+	recursive: HashSet<String>,
+	//Which frame slot each temporary of each function uses
+	slots: HashMap<String, Vec<usize>>,
+	memory_map: MemoryMap,
+	workspace: Workspace,
+	//Where each temporary of the function being generated lives
+	locs: Vec<Loc>,
+	//Which temporaries of each function live in X or Y instead of memory
+	registers: HashMap<String, Vec<Option<Register>>>,
+	//What A, X and Y hold at the moment, to avoid loading them again
+	tracker: Tracker,
+	//Whether to optimize code as it's generated
+	optimize: bool,
+}
+
+impl<'a> Generator<'a> {
+	pub fn run(
+		module: &'a ir::Module,
+		context: &'a Context,
+		target: &'a Target,
+		options: &Options,
+	) -> Vec<Bytecode> {
+		let calls = CallGraph::build(module);
+		let recursive = calls.recursive();
+
+		let optimize = options.opt_level > 0;
+
+		//Work out how heavily each slot of each fixed frame is used, to decide what goes in zero page.
+		let mut slots = HashMap::new();
+		let mut usage = HashMap::new();
+		let mut registers = HashMap::new();
+		for function in &module.functions {
+			let cfg = Cfg::build(function);
+			let function_registers = if optimize {
+				registers::assign_registers(function, &cfg, recursive.contains(&function.name))
+			} else {
+				vec![None; function.temps.len()]
+			};
+			registers.insert(function.name.clone(), function_registers);
+
+			let function_slots = frame::assign_slots(function, &cfg);
+			if !recursive.contains(&function.name) {
+				usage.insert(
+					function.name.clone(),
+					frame_usage(function, &cfg, &function_slots),
+				);
+			}
+			slots.insert(function.name.clone(), function_slots);
+		}
+
+		let uses_math = uses(module, |inst| {
+			matches!(
+				inst,
+				ir::Inst::Binary(
+					_,
+					ir::BinaryOp::Mul | ir::BinaryOp::Div | ir::BinaryOp::Rem,
+					_,
+					_
+				)
+			)
+		});
+		//Printing a number divides it by 10 for every digit.
+		let uses_print = uses(
+			module,
+			|inst| matches!(inst, ir::Inst::Call(_, name, _) if name == "print"),
+		);
+
+		let reserved: Vec<(u8, u8)> = target
+			.zp_reserved
+			.iter()
+			.chain(&options.zp_reserve)
+			.copied()
+			.collect();
+		//The software stack has to have room for at least one frame of each recursive function.
+		let stack_needed = recursive
+			.iter()
+			.map(|name| 2 * slot_count(&slots[name]) as u32)
+			.max()
+			.unwrap_or(0);
+		let memory_map = frame::place(
+			&calls,
+			&usage,
+			!recursive.is_empty(),
+			uses_math || uses_print,
+			&reserved,
+			target.ram,
+			stack_needed,
+			options.zp_spill,
+		);
+
+		let mut generator = Generator {
+			context,
+			module,
+			target,
+			code: vec![],
+			runtime: BTreeSet::new(),
+			labels: 0,
+			recursive,
+			slots,
+			workspace: memory_map.workspace,
+			memory_map,
+			locs: vec![],
+			registers,
+			tracker: Tracker::new(),
+			optimize,
+		};
+
+		for function in &module.functions {
+			if generator.recursive.contains(&function.name) {
+				generator.check_frame_size(function, &calls);
+			}
+		}
+		if message::errored() {
+			return vec![];
+		}
+
+		generator.generate();
+		generator.code
+	}
+
+	fn generate(&mut self) {
+		self.emit(Org(self.target.rom.0));
+		for bytecode in self.target.header.clone() {
+			self.emit(bytecode);
+		}
+		self.emit(Label(target::RESET.to_string()));
+		for bytecode in self.target.startup.clone() {
+			self.emit(bytecode);
+		}
+
+		if !self.recursive.is_empty() {
+			self.emit(Define(
+				STACK_PTR.to_string(),
+				Value::Number(self.memory_map.stack_ptr),
+			));
+
+			let top = Value::Number(self.target.stack_top());
+			self.emit(LDA(Immediate(top.clone().low())));
+			self.emit(STA(ZeroPage(Value::symbol(STACK_PTR))));
+			self.emit(LDA(Immediate(top.high())));
+			self.emit(STA(ZeroPage(Value::symbol(STACK_PTR).offset(1))));
+		}
+
+		//Program entry point. What happens after `main` (or the test being run) returns is up to
+		//the target.
+		self.emit(JSR(Absolute(Value::symbol(&Generator::function_label(
+			&self.module.entry,
+		)))));
+		for bytecode in self.target.exit.clone() {
+			self.emit(bytecode);
+		}
+
+		for function in &self.module.functions {
+			self.function(function);
+		}
+
+		//Include any runtime routines that the program needs, along with whatever they depend on.
+		let mut needed: Vec<Routine> = self.runtime.iter().copied().collect();
+		let mut included = BTreeSet::new();
+		while let Some(routine) = needed.pop() {
+			if included.insert(routine) {
+				needed.extend(routine.dependencies());
+			}
+		}
+		for routine in included {
+			self.code.extend(routine.code(&self.workspace));
+		}
+
+		//Running out of stack is handled like a failed assertion.
+		if !self.recursive.is_empty() {
+			self.emit(Label(STACK_OVERFLOW.to_string()));
+			self.emit(LDA(Immediate(Value::Number(target::OUT_OF_STACK))));
+			self.emit(JMP(Absolute(Value::symbol(target::ASSERT_FAILED))));
+		}
+
+		let prints = self.runtime.contains(&Routine::Print);
+		let asserts = !self.recursive.is_empty()
+			|| uses(
+				self.module,
+				|inst| matches!(inst, ir::Inst::Call(_, name, _) if name == "assert" || name == "assert_eq"),
+			);
+		self.code.extend(self.target.runtime(prints, asserts));
+	}
+
+	pub fn emit(&mut self, bytecode: Bytecode) {
+		self.tracker.update(&bytecode);
+		self.code.push(bytecode);
+	}
+
+	/// Label for the entry point of a user-defined function.
+	pub fn function_label(name: &str) -> String {
+		format!("FN_{}", name)
+	}
+
+	/// Symbol holding the size of a recursive function's stack frame.
+	pub fn frame_size_label(name: &str) -> String {
+		format!("FN_{}_FRAME", name)
+	}
+
+	/// Whether the given function keeps its frame on the software stack.
+	pub fn is_stack_frame(&self, function: &ir::Function) -> bool {
+		self.recursive.contains(&function.name)
+	}
+
+	/// Size in bytes of a function's frame.
+	pub fn frame_size(&self, function: &ir::Function) -> u16 {
+		2 * slot_count(&self.slots[&function.name]) as u16
+	}
+
+	/// Where a temporary of the given function lives.
+	/// Anything on the stack is relative to that function's frame. A loop counter's place is in
+	/// the frame, which only has its value when `counter` says so.
+	pub fn temp_loc(&self, function: &ir::Function, temp: ir::Temp) -> Loc {
+		if let Some(register) = self.registers[&function.name][temp.0] {
+			if function.temps[temp.0].width == ir::Width::Byte {
+				return Loc::Reg(register);
+			}
+		}
+		let offset = 2 * self.slots[&function.name][temp.0] as u16;
+		if self.is_stack_frame(function) {
+			Loc::Stack(offset)
+		} else {
+			Loc::Mem(self.memory_map.frames[&function.name].address(offset))
+		}
+	}
+
+	/// The register an `int` loop counter lives in, if it's one (see `registers::loop_counters`).
+	pub fn counter(&self, function: &ir::Function, temp: ir::Temp) -> Option<Register> {
+		match function.temps[temp.0].width {
+			ir::Width::Word => self.registers[&function.name][temp.0],
+			ir::Width::Byte => None,
+		}
+	}
+
+	/// Where an operand of the function being generated lives.
+	pub fn loc(&self, operand: ir::Operand) -> Loc {
+		match operand {
+			ir::Operand::Const(value) => Loc::Const(value),
+			ir::Operand::Temp(temp) => self.locs[temp.0],
+		}
+	}
+
+	/// Values on the stack are reached with `(STACK_PTR),y`, so they have to be within 256 bytes
+	/// of the frame pointer. When one recursive function calls another, the caller's frame sits
+	/// just above the callee's while the arguments are copied over, so both have to fit.
+	fn check_frame_size(&self, function: &ir::Function, calls: &CallGraph) {
+		const LIMIT: u16 = 256;
+		let size = self.frame_size(function);
+
+		if size > LIMIT {
+			message::error(
+				format!(
+					"Recursive function `{}` needs {} bytes of stack, but at most {} are allowed",
+					function.name, size, LIMIT
+				),
+				Some(function.span),
+				Some(self.context),
+			);
+			return;
+		}
+
+		for callee in calls.callees(&function.name) {
+			let callee = self.module.function(callee).unwrap();
+			let callee_size = self.frame_size(callee);
+			if self.is_stack_frame(callee) && size + callee_size > LIMIT {
+				let text = if callee.name == function.name {
+					format!(
+						"Recursive function `{}` needs {} bytes of stack to call itself, but at most {} are allowed",
+						function.name,
+						size * 2,
+						LIMIT
+					)
+				} else {
+					format!(
+						"Recursive functions `{}` and `{}` need {} bytes of stack between them, but at most {} are allowed",
+						function.name,
+						callee.name,
+						size + callee_size,
+						LIMIT
+					)
+				};
+				message::error(text, Some(function.span), Some(self.context));
+			}
+		}
+	}
+
+	/// Get a new unique label name.
+	pub fn new_label(&mut self) -> String {
+		self.labels += 1;
+		format!("L{}", self.labels)
+	}
+
+	/// Emit an instruction that reads or writes byte `n` of a value,
+	/// first loading Y if the value is on the stack.
+	/// Loads and stores are left out if the register or memory already has the value, so nothing
+	/// should depend on the flags that a load through here sets.
+	pub fn emit_op(&mut self, op: fn(asm::Mode) -> Bytecode, loc: Loc, n: u16) {
+		if let Loc::Reg(register) = loc {
+			let transfer = match (op(Implied), register) {
+				(LDA(_), Register::X) => TXA(Implied),
+				(LDA(_), Register::Y) => TYA(Implied),
+				(STA(_), Register::X) => TAX(Implied),
+				(STA(_), Register::Y) => TAY(Implied),
+				_ => panic!("COMPILER BUG: Only loads and stores can use a value in a register"),
+			};
+			self.emit(transfer);
+			return;
+		}
+
+		if let Some(index) = loc.index(n) {
+			self.emit_load(LDY(Immediate(Value::Number(index))));
+		}
+		let bytecode = op(loc.byte(n));
+		if self.optimize && self.tracker.redundant_store(&bytecode) {
+			return;
+		}
+		self.emit_load(bytecode);
+	}
+
+	//Emit a load, unless the register already has the value or can get it from another one.
+	fn emit_load(&mut self, bytecode: Bytecode) {
+		if !self.optimize {
+			self.emit(bytecode);
+		} else if let Some(bytecode) = self.tracker.load(bytecode) {
+			self.emit(bytecode);
+		}
+	}
+
+	/// Call one of the runtime routines, making sure it gets included in the output.
+	pub fn call_runtime(&mut self, routine: Routine) {
+		self.emit(JSR(Absolute(Value::symbol(routine.label()))));
+		self.runtime.insert(routine);
+	}
+}
+
+//How heavily each slot in a function's frame is used.
+//Uses inside loops probably happen many more times, so they count for more.
+fn frame_usage(function: &ir::Function, cfg: &Cfg, slots: &[usize]) -> FrameUsage {
+	let mut weights = vec![0; slot_count(slots)];
+	let loop_depths = dominators::loop_depths(function, cfg);
+
+	for (block, loop_depth) in function.blocks.iter().zip(loop_depths) {
+		let weight = 1u64 << (3 * loop_depth.min(8));
+		let mut count = |operand: ir::Operand| {
+			if let ir::Operand::Temp(temp) = operand {
+				weights[slots[temp.0]] += weight;
+			}
+		};
+
+		for inst in &block.insts {
+			if let Some(dest) = inst.dest() {
+				count(ir::Operand::Temp(dest));
+			}
+			inst.operands().into_iter().for_each(&mut count);
+		}
+		block.terminator.operands().into_iter().for_each(&mut count);
+	}
+
+	FrameUsage {
+		slots: slot_count(slots),
+		weights,
+	}
+}
+
+//Whether any instruction in the program is one the predicate is looking for.
+fn uses(module: &ir::Module, predicate: impl Fn(&ir::Inst) -> bool) -> bool {
+	module.functions.iter().any(|function| {
+		function
+			.blocks
+			.iter()
+			.any(|block| block.insts.iter().any(&predicate))
+	})
+}
+
+//How many slots a frame needs, given the slot of each temporary.
+fn slot_count(slots: &[usize]) -> usize {
+	slots.iter().map(|slot| slot + 1).max().unwrap_or(0)
+}
@@ -1,39 +1,497 @@
+/// An operand value. Numbers are used as-is, symbols are resolved by the assembler.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Value {
+	Number(u16),
+	/// A symbol plus a constant offset, e.g. `MATH_CONVERT_VAL+1`.
+	Symbol(String, i32),
+	/// The low byte of a value, e.g. `<MATH_CONVERT_OUT`.
+	Low(Box<Value>),
+	/// The high byte of a value, e.g. `>MATH_CONVERT_OUT`.
+	High(Box<Value>),
+}
+
+/// Addressing modes of the 6502, each carrying the operand it needs (if any).
+//The assembler handles every mode, even the ones code generation doesn't use yet.
+#[allow(dead_code)]
+#[derive(Clone, PartialEq, Debug)]
+pub enum Mode {
+	Implied,
+	Accumulator,
+	Immediate(Value),
+	ZeroPage(Value),
+	ZeroPageX(Value),
+	ZeroPageY(Value),
+	Absolute(Value),
+	AbsoluteX(Value),
+	AbsoluteY(Value),
+	Indirect(Value),
+	IndirectX(Value),
+	IndirectY(Value),
+	Relative(Value),
+}
+
+/// The full documented NMOS 6502 instruction set, plus labels and assembler directives.
+//Named after the mnemonics, and complete even where code generation doesn't use an instruction.
+#[allow(dead_code, clippy::upper_case_acronyms)]
+#[derive(Clone, PartialEq, Debug)]
 pub enum Bytecode {
-	LDA(String),
-	STA(String),
-	JSR(String),
+	ADC(Mode),
+	AND(Mode),
+	ASL(Mode),
+	BCC(Mode),
+	BCS(Mode),
+	BEQ(Mode),
+	BIT(Mode),
+	BMI(Mode),
+	BNE(Mode),
+	BPL(Mode),
+	BRK(Mode),
+	BVC(Mode),
+	BVS(Mode),
+	CLC(Mode),
+	CLD(Mode),
+	CLI(Mode),
+	CLV(Mode),
+	CMP(Mode),
+	CPX(Mode),
+	CPY(Mode),
+	DEC(Mode),
+	DEX(Mode),
+	DEY(Mode),
+	EOR(Mode),
+	INC(Mode),
+	INX(Mode),
+	INY(Mode),
+	JMP(Mode),
+	JSR(Mode),
+	LDA(Mode),
+	LDX(Mode),
+	LDY(Mode),
+	LSR(Mode),
+	NOP(Mode),
+	ORA(Mode),
+	PHA(Mode),
+	PHP(Mode),
+	PLA(Mode),
+	PLP(Mode),
+	ROL(Mode),
+	ROR(Mode),
+	RTI(Mode),
+	RTS(Mode),
+	SBC(Mode),
+	SEC(Mode),
+	SED(Mode),
+	SEI(Mode),
+	STA(Mode),
+	STX(Mode),
+	STY(Mode),
+	TAX(Mode),
+	TAY(Mode),
+	TSX(Mode),
+	TXA(Mode),
+	TXS(Mode),
+	TYA(Mode),
+
+	/// Defines a label at the current address.
+	Label(String),
+	/// Defines a symbol with a constant value (`NAME = value`).
+	Define(String, Value),
+	/// Continue output at the given address (`.org`).
+	Org(u16),
+	/// Raw bytes (`.byte`).
+	Byte(Vec<Value>),
+	/// Raw little-endian words (`.word`).
+	Word(Vec<Value>),
+	/// Reserve a number of zeroed bytes (`.res`).
+	Res(u16),
+	/// Pad with zeros until the address is a multiple of the given size (`.align`).
+	Align(u16),
 }
 
+use super::assembler;
+use std::collections::HashMap;
 use Bytecode::*;
+use Mode::*;
 
-impl Bytecode {
-	pub fn text(self) -> String {
+//Marks an addressing mode that the instruction does not support.
+const NA: u16 = 0x100;
+
+//Opcodes for every instruction, indexed by `Mode::index()`.
+#[rustfmt::skip]
+const OPCODES: [(&str, [u16; 13]); 56] = [
+	//        imp   acc   imm   zp    zp,x  zp,y  abs   abs,x abs,y ind   (zp,x)(zp),y rel
+	("adc", [NA,   NA,   0x69, 0x65, 0x75, NA,   0x6D, 0x7D, 0x79, NA,   0x61, 0x71, NA  ]),
+	("and", [NA,   NA,   0x29, 0x25, 0x35, NA,   0x2D, 0x3D, 0x39, NA,   0x21, 0x31, NA  ]),
+	("asl", [NA,   0x0A, NA,   0x06, 0x16, NA,   0x0E, 0x1E, NA,   NA,   NA,   NA,   NA  ]),
+	("bcc", [NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   0x90]),
+	("bcs", [NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   0xB0]),
+	("beq", [NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   0xF0]),
+	("bit", [NA,   NA,   NA,   0x24, NA,   NA,   0x2C, NA,   NA,   NA,   NA,   NA,   NA  ]),
+	("bmi", [NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   0x30]),
+	("bne", [NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   0xD0]),
+	("bpl", [NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   0x10]),
+	("brk", [0x00, NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA  ]),
+	("bvc", [NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   0x50]),
+	("bvs", [NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   0x70]),
+	("clc", [0x18, NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA  ]),
+	("cld", [0xD8, NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA  ]),
+	("cli", [0x58, NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA  ]),
+	("clv", [0xB8, NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA  ]),
+	("cmp", [NA,   NA,   0xC9, 0xC5, 0xD5, NA,   0xCD, 0xDD, 0xD9, NA,   0xC1, 0xD1, NA  ]),
+	("cpx", [NA,   NA,   0xE0, 0xE4, NA,   NA,   0xEC, NA,   NA,   NA,   NA,   NA,   NA  ]),
+	("cpy", [NA,   NA,   0xC0, 0xC4, NA,   NA,   0xCC, NA,   NA,   NA,   NA,   NA,   NA  ]),
+	("dec", [NA,   NA,   NA,   0xC6, 0xD6, NA,   0xCE, 0xDE, NA,   NA,   NA,   NA,   NA  ]),
+	("dex", [0xCA, NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA  ]),
+	("dey", [0x88, NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA  ]),
+	("eor", [NA,   NA,   0x49, 0x45, 0x55, NA,   0x4D, 0x5D, 0x59, NA,   0x41, 0x51, NA  ]),
+	("inc", [NA,   NA,   NA,   0xE6, 0xF6, NA,   0xEE, 0xFE, NA,   NA,   NA,   NA,   NA  ]),
+	("inx", [0xE8, NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA  ]),
+	("iny", [0xC8, NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA  ]),
+	("jmp", [NA,   NA,   NA,   NA,   NA,   NA,   0x4C, NA,   NA,   0x6C, NA,   NA,   NA  ]),
+	("jsr", [NA,   NA,   NA,   NA,   NA,   NA,   0x20, NA,   NA,   NA,   NA,   NA,   NA  ]),
+	("lda", [NA,   NA,   0xA9, 0xA5, 0xB5, NA,   0xAD, 0xBD, 0xB9, NA,   0xA1, 0xB1, NA  ]),
+	("ldx", [NA,   NA,   0xA2, 0xA6, NA,   0xB6, 0xAE, NA,   0xBE, NA,   NA,   NA,   NA  ]),
+	("ldy", [NA,   NA,   0xA0, 0xA4, 0xB4, NA,   0xAC, 0xBC, NA,   NA,   NA,   NA,   NA  ]),
+	("lsr", [NA,   0x4A, NA,   0x46, 0x56, NA,   0x4E, 0x5E, NA,   NA,   NA,   NA,   NA  ]),
+	("nop", [0xEA, NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA  ]),
+	("ora", [NA,   NA,   0x09, 0x05, 0x15, NA,   0x0D, 0x1D, 0x19, NA,   0x01, 0x11, NA  ]),
+	("pha", [0x48, NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA  ]),
+	("php", [0x08, NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA  ]),
+	("pla", [0x68, NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA  ]),
+	("plp", [0x28, NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA  ]),
+	("rol", [NA,   0x2A, NA,   0x26, 0x36, NA,   0x2E, 0x3E, NA,   NA,   NA,   NA,   NA  ]),
+	("ror", [NA,   0x6A, NA,   0x66, 0x76, NA,   0x6E, 0x7E, NA,   NA,   NA,   NA,   NA  ]),
+	("rti", [0x40, NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA  ]),
+	("rts", [0x60, NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA  ]),
+	("sbc", [NA,   NA,   0xE9, 0xE5, 0xF5, NA,   0xED, 0xFD, 0xF9, NA,   0xE1, 0xF1, NA  ]),
+	("sec", [0x38, NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA  ]),
+	("sed", [0xF8, NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA  ]),
+	("sei", [0x78, NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA  ]),
+	("sta", [NA,   NA,   NA,   0x85, 0x95, NA,   0x8D, 0x9D, 0x99, NA,   0x81, 0x91, NA  ]),
+	("stx", [NA,   NA,   NA,   0x86, NA,   0x96, 0x8E, NA,   NA,   NA,   NA,   NA,   NA  ]),
+	("sty", [NA,   NA,   NA,   0x84, 0x94, NA,   0x8C, NA,   NA,   NA,   NA,   NA,   NA  ]),
+	("tax", [0xAA, NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA  ]),
+	("tay", [0xA8, NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA  ]),
+	("tsx", [0xBA, NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA  ]),
+	("txa", [0x8A, NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA  ]),
+	("txs", [0x9A, NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA  ]),
+	("tya", [0x98, NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA,   NA  ]),
+];
+
+/// The mnemonic and addressing mode (as a column of the opcode table, see `Mode::index()`)
+/// of a machine opcode, or `None` if it isn't a documented instruction.
+pub fn decode(opcode: u8) -> Option<(&'static str, usize)> {
+	for (name, row) in OPCODES.iter() {
+		if let Some(mode) = row.iter().position(|op| *op == opcode as u16) {
+			return Some((name, mode));
+		}
+	}
+	None
+}
+
+impl Value {
+	pub fn symbol(name: &str) -> Value {
+		Value::Symbol(name.to_string(), 0)
+	}
+
+	/// Add a constant offset to this value.
+	pub fn offset(self, amount: i32) -> Value {
 		match self {
-			LDA(address) => {
-				format!("lda {}", address)
-			},
+			Value::Number(n) => Value::Number(n.wrapping_add(amount as u16)),
+			Value::Symbol(name, offset) => Value::Symbol(name, offset + amount),
+			_ => panic!("COMPILER BUG: Cannot offset a byte selection"),
+		}
+	}
 
-			STA(address) => {
-				format!("sta {}", address)
+	pub fn low(self) -> Value {
+		Value::Low(Box::new(self))
+	}
+
+	pub fn high(self) -> Value {
+		Value::High(Box::new(self))
+	}
+
+	/// Get the numeric value, looking up symbols if needed.
+	pub fn resolve(&self, symbols: &HashMap<String, u16>) -> Result<u16, String> {
+		match self {
+			Value::Number(n) => Ok(*n),
+			Value::Symbol(name, offset) => match symbols.get(name) {
+				None => Err(format!("Undefined symbol `{}`", name)),
+				Some(n) => Ok((*n as i32 + offset) as u16),
 			},
+			Value::Low(value) => Ok(value.resolve(symbols)? & 0xFF),
+			Value::High(value) => Ok(value.resolve(symbols)? >> 8),
+		}
+	}
+
+	fn text(&self, digits: usize) -> String {
+		match self {
+			Value::Number(n) => format!("${:0digits$X}", n, digits = digits),
+			Value::Symbol(name, 0) => name.clone(),
+			Value::Symbol(name, offset) if *offset < 0 => format!("{}-{}", name, -offset),
+			Value::Symbol(name, offset) => format!("{}+{}", name, offset),
+			Value::Low(value) => format!("<{}", value.text(4)),
+			Value::High(value) => format!(">{}", value.text(4)),
+		}
+	}
+}
+
+impl Mode {
+	/// Column of this addressing mode in the opcode table.
+	pub fn index(&self) -> usize {
+		match self {
+			Implied => 0,
+			Accumulator => 1,
+			Immediate(_) => 2,
+			ZeroPage(_) => 3,
+			ZeroPageX(_) => 4,
+			ZeroPageY(_) => 5,
+			Absolute(_) => 6,
+			AbsoluteX(_) => 7,
+			AbsoluteY(_) => 8,
+			Indirect(_) => 9,
+			IndirectX(_) => 10,
+			IndirectY(_) => 11,
+			Relative(_) => 12,
+		}
+	}
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			Implied => "implied",
+			Accumulator => "accumulator",
+			Immediate(_) => "immediate",
+			ZeroPage(_) => "zero page",
+			ZeroPageX(_) => "zero page,X",
+			ZeroPageY(_) => "zero page,Y",
+			Absolute(_) => "absolute",
+			AbsoluteX(_) => "absolute,X",
+			AbsoluteY(_) => "absolute,Y",
+			Indirect(_) => "indirect",
+			IndirectX(_) => "(zero page,X)",
+			IndirectY(_) => "(zero page),Y",
+			Relative(_) => "relative",
+		}
+	}
 
-			JSR(label) => {
-				format!("sta {}", label)
+	pub fn value(&self) -> Option<&Value> {
+		match self {
+			Implied | Accumulator => None,
+			Immediate(v) | ZeroPage(v) | ZeroPageX(v) | ZeroPageY(v) | Absolute(v)
+			| AbsoluteX(v) | AbsoluteY(v) | Indirect(v) | IndirectX(v) | IndirectY(v)
+			| Relative(v) => Some(v),
+		}
+	}
+
+	/// Number of operand bytes following the opcode.
+	pub fn operand_size(&self) -> u16 {
+		match self {
+			Implied | Accumulator => 0,
+			Absolute(_) | AbsoluteX(_) | AbsoluteY(_) | Indirect(_) => 2,
+			_ => 1,
+		}
+	}
+
+	fn text(&self) -> String {
+		match self {
+			Implied => String::new(),
+			Accumulator => " a".to_string(),
+			Immediate(v) => format!(" #{}", v.text(2)),
+			ZeroPage(v) => format!(" {}", v.text(2)),
+			ZeroPageX(v) => format!(" {},x", v.text(2)),
+			ZeroPageY(v) => format!(" {},y", v.text(2)),
+			Absolute(v) => format!(" {}", v.text(4)),
+			AbsoluteX(v) => format!(" {},x", v.text(4)),
+			AbsoluteY(v) => format!(" {},y", v.text(4)),
+			Indirect(v) => format!(" ({})", v.text(4)),
+			IndirectX(v) => format!(" ({},x)", v.text(2)),
+			IndirectY(v) => format!(" ({}),y", v.text(2)),
+			Relative(v) => format!(" {}", v.text(4)),
+		}
+	}
+}
+
+impl Bytecode {
+	pub fn mnemonic(&self) -> Option<&'static str> {
+		let name = match self {
+			ADC(_) => "adc",
+			AND(_) => "and",
+			ASL(_) => "asl",
+			BCC(_) => "bcc",
+			BCS(_) => "bcs",
+			BEQ(_) => "beq",
+			BIT(_) => "bit",
+			BMI(_) => "bmi",
+			BNE(_) => "bne",
+			BPL(_) => "bpl",
+			BRK(_) => "brk",
+			BVC(_) => "bvc",
+			BVS(_) => "bvs",
+			CLC(_) => "clc",
+			CLD(_) => "cld",
+			CLI(_) => "cli",
+			CLV(_) => "clv",
+			CMP(_) => "cmp",
+			CPX(_) => "cpx",
+			CPY(_) => "cpy",
+			DEC(_) => "dec",
+			DEX(_) => "dex",
+			DEY(_) => "dey",
+			EOR(_) => "eor",
+			INC(_) => "inc",
+			INX(_) => "inx",
+			INY(_) => "iny",
+			JMP(_) => "jmp",
+			JSR(_) => "jsr",
+			LDA(_) => "lda",
+			LDX(_) => "ldx",
+			LDY(_) => "ldy",
+			LSR(_) => "lsr",
+			NOP(_) => "nop",
+			ORA(_) => "ora",
+			PHA(_) => "pha",
+			PHP(_) => "php",
+			PLA(_) => "pla",
+			PLP(_) => "plp",
+			ROL(_) => "rol",
+			ROR(_) => "ror",
+			RTI(_) => "rti",
+			RTS(_) => "rts",
+			SBC(_) => "sbc",
+			SEC(_) => "sec",
+			SED(_) => "sed",
+			SEI(_) => "sei",
+			STA(_) => "sta",
+			STX(_) => "stx",
+			STY(_) => "sty",
+			TAX(_) => "tax",
+			TAY(_) => "tay",
+			TSX(_) => "tsx",
+			TXA(_) => "txa",
+			TXS(_) => "txs",
+			TYA(_) => "tya",
+			Label(_) | Define(_, _) | Org(_) | Byte(_) | Word(_) | Res(_) | Align(_) => {
+				return None
+			}
+		};
+
+		Some(name)
+	}
+
+	pub fn mode(&self) -> Option<&Mode> {
+		match self {
+			ADC(m) | AND(m) | ASL(m) | BCC(m) | BCS(m) | BEQ(m) | BIT(m) | BMI(m) | BNE(m)
+			| BPL(m) | BRK(m) | BVC(m) | BVS(m) | CLC(m) | CLD(m) | CLI(m) | CLV(m) | CMP(m)
+			| CPX(m) | CPY(m) | DEC(m) | DEX(m) | DEY(m) | EOR(m) | INC(m) | INX(m) | INY(m)
+			| JMP(m) | JSR(m) | LDA(m) | LDX(m) | LDY(m) | LSR(m) | NOP(m) | ORA(m) | PHA(m)
+			| PHP(m) | PLA(m) | PLP(m) | ROL(m) | ROR(m) | RTI(m) | RTS(m) | SBC(m) | SEC(m)
+			| SED(m) | SEI(m) | STA(m) | STX(m) | STY(m) | TAX(m) | TAY(m) | TSX(m) | TXA(m)
+			| TXS(m) | TYA(m) => Some(m),
+			Label(_) | Define(_, _) | Org(_) | Byte(_) | Word(_) | Res(_) | Align(_) => None,
+		}
+	}
+
+	/// The machine opcode for this instruction, or `None` if the addressing mode is not supported.
+	pub fn opcode(&self) -> Option<u8> {
+		let mnemonic = self.mnemonic()?;
+		let (_, row) = OPCODES.iter().find(|(name, _)| *name == mnemonic).unwrap();
+		let opcode = row[self.mode()?.index()];
+		if opcode == NA {
+			None
+		} else {
+			Some(opcode as u8)
+		}
+	}
+
+	/// Check that the addressing mode and operand are valid for this instruction.
+	pub fn validate(&self) -> Result<(), String> {
+		match self {
+			Byte(values) => {
+				for value in values {
+					if let Value::Number(n) = value {
+						if *n > 0xFF {
+							return Err(format!("Value ${:04X} does not fit in `.byte`", n));
+						}
+					}
+				}
+			}
+			Align(0) => return Err("Alignment must be greater than zero".to_string()),
+			_ => {}
+		}
+
+		let (mnemonic, mode) = match (self.mnemonic(), self.mode()) {
+			(Some(mnemonic), Some(mode)) => (mnemonic, mode),
+			_ => return Ok(()),
+		};
+
+		if self.opcode().is_none() {
+			return Err(format!(
+				"Instruction `{}` does not support {} addressing",
+				mnemonic,
+				mode.name()
+			));
+		}
+
+		if let Some(Value::Number(n)) = mode.value() {
+			if mode.operand_size() == 1 && !matches!(mode, Relative(_)) && *n > 0xFF {
+				return Err(format!(
+					"Operand ${:04X} of `{}` does not fit in {} addressing",
+					n,
+					mnemonic,
+					mode.name()
+				));
 			}
 		}
+
+		Ok(())
+	}
+
+	/// Size of the encoded item in bytes, if it were placed at the given address.
+	pub fn size(&self, address: u16) -> u16 {
+		match self {
+			Byte(values) => values.len() as u16,
+			Word(values) => 2 * values.len() as u16,
+			Res(count) => *count,
+			Align(alignment) => (alignment - address % alignment) % alignment,
+			_ => match self.mode() {
+				None => 0,
+				Some(mode) => 1 + mode.operand_size(),
+			},
+		}
 	}
 
-	pub fn binary(self) -> String {
-		panic!("Binary bytecode output is not implemented!")
+	pub fn text(self) -> String {
+		if let Err(msg) = self.validate() {
+			panic!("COMPILER BUG: {}", msg);
+		}
+
+		let list = |values: &Vec<Value>, digits| {
+			let text: Vec<String> = values.iter().map(|v| v.text(digits)).collect();
+			text.join(", ")
+		};
+
+		match &self {
+			Label(name) => format!("{}:\n", name),
+			Define(name, value) => format!("{} = {}\n", name, value.text(4)),
+			Org(address) => format!("\t.org ${:04X}\n", address),
+			Byte(values) => format!("\t.byte {}\n", list(values, 2)),
+			Word(values) => format!("\t.word {}\n", list(values, 4)),
+			Res(count) => format!("\t.res {}\n", count),
+			Align(alignment) => format!("\t.align {}\n", alignment),
+			_ => format!(
+				"\t{}{}\n",
+				self.mnemonic().unwrap(),
+				self.mode().unwrap().text()
+			),
+		}
 	}
 
 	pub fn output_text(bytecode: Vec<Bytecode>) -> String {
 		let result: Vec<String> = bytecode.into_iter().map(|bc| bc.text()).collect();
-		return result.join("");
+		result.join("")
 	}
 
-	pub fn output_binary(bytecode: Vec<Bytecode>) -> String {
-		let result: Vec<String> = bytecode.into_iter().map(|bc| bc.binary()).collect();
-		return result.join("");
+	pub fn output_binary(bytecode: Vec<Bytecode>, origin: u16) -> Vec<u8> {
+		assembler::assemble(&bytecode, origin)
 	}
 }
@@ -0,0 +1,199 @@
+use super::asm::Bytecode::*;
+use super::asm::Mode::*;
+use super::asm::{Bytecode, Value};
+use crate::message;
+use std::collections::HashMap;
+
+/// Convert bytecode into raw machine code. The first byte of the output is at `origin`,
+/// and any gaps left by `.org` directives are filled with zeros.
+/// Any problems (undefined symbols, branches out of range, etc.) are reported as errors.
+pub fn assemble(bytecode: &[Bytecode], origin: u16) -> Vec<u8> {
+	let symbols = resolve_symbols(bytecode, origin);
+	let mut output: Vec<u8> = vec![];
+	let mut address = origin as usize;
+
+	for bc in bytecode {
+		if let Err(msg) = bc.validate() {
+			panic!("COMPILER BUG: {}", msg);
+		}
+
+		match bc {
+			Org(new_address) => {
+				//Moving backwards was already reported in the first pass.
+				let gap = (*new_address as usize).saturating_sub(address);
+				output.resize(output.len() + gap, 0);
+				address = *new_address as usize;
+				continue;
+			}
+
+			Byte(values) => {
+				for value in values {
+					let value = resolve(value, &symbols);
+					if value > 0xFF {
+						message::error(
+							format!(
+								"Value ${:04X} at ${:04X} does not fit in `.byte`",
+								value, address
+							),
+							None,
+							None,
+						);
+					}
+					output.push(value as u8);
+				}
+			}
+
+			Word(values) => {
+				for value in values {
+					output.extend(resolve(value, &symbols).to_le_bytes());
+				}
+			}
+
+			Res(_) | Align(_) => {
+				output.resize(output.len() + bc.size(address as u16) as usize, 0);
+			}
+
+			Label(_) | Define(_, _) => {}
+
+			_ => encode(bc, address as u16, &symbols, &mut output),
+		}
+
+		address += bc.size(address as u16) as usize;
+	}
+
+	output
+}
+
+fn resolve(value: &Value, symbols: &HashMap<String, u16>) -> u16 {
+	match value.resolve(symbols) {
+		Ok(value) => value,
+		Err(msg) => {
+			message::error(msg, None, None);
+			0
+		}
+	}
+}
+
+//Output a single instruction.
+fn encode(bc: &Bytecode, address: u16, symbols: &HashMap<String, u16>, output: &mut Vec<u8>) {
+	let mode = bc.mode().unwrap();
+	output.push(bc.opcode().unwrap());
+
+	let value = match mode.value() {
+		None => return,
+		Some(value) => resolve(value, symbols),
+	};
+
+	match mode {
+		Relative(_) => {
+			//Branch offsets are relative to the address of the next instruction.
+			let offset = value as isize - (address as isize + 2);
+			if !(-128..=127).contains(&offset) {
+				message::error(
+					format!(
+						"Branch target ${:04X} is out of range of `{}` at ${:04X} ({} bytes away)",
+						value,
+						bc.mnemonic().unwrap(),
+						address,
+						offset
+					),
+					None,
+					None,
+				);
+			}
+			output.push(offset as u8);
+		}
+
+		_ => {
+			if mode.operand_size() == 1 {
+				if value > 0xFF {
+					message::error(
+						format!(
+							"Operand ${:04X} of `{}` at ${:04X} does not fit in {} addressing",
+							value,
+							bc.mnemonic().unwrap(),
+							address,
+							mode.name()
+						),
+						None,
+						None,
+					);
+				}
+				output.push(value as u8);
+			} else {
+				output.extend(value.to_le_bytes());
+			}
+		}
+	}
+}
+
+//First pass: figure out the address of every label, then the value of every defined symbol.
+fn resolve_symbols(bytecode: &[Bytecode], origin: u16) -> HashMap<String, u16> {
+	let mut symbols = HashMap::new();
+	let mut defines = vec![];
+	let mut address = origin as usize;
+
+	let define = |symbols: &mut HashMap<String, u16>, name: &String, value: u16| {
+		if symbols.contains_key(name) {
+			message::error(format!("Redefinition of symbol `{}`", name), None, None);
+		}
+		symbols.insert(name.clone(), value);
+	};
+
+	for bc in bytecode {
+		match bc {
+			Label(name) => define(&mut symbols, name, address as u16),
+			Define(name, value) => defines.push((name, value)),
+			Org(new_address) => {
+				if (*new_address as usize) < address {
+					message::error(
+						format!(
+							"`.org ${:04X}` would move backwards from ${:04X}",
+							new_address, address
+						),
+						None,
+						None,
+					);
+				}
+				address = *new_address as usize;
+			}
+			_ => {}
+		}
+
+		address += bc.size(address as u16) as usize;
+		if address > 0x10000 {
+			message::error(
+				format!(
+					"Program does not fit in memory (starting at ${:04X})",
+					origin
+				),
+				None,
+				None,
+			);
+			break;
+		}
+	}
+
+	//Defined symbols may refer to each other, so keep resolving until nothing changes.
+	loop {
+		let before = defines.len();
+		let mut unresolved = vec![];
+		for (name, value) in defines {
+			match value.resolve(&symbols) {
+				Ok(n) => define(&mut symbols, name, n),
+				Err(_) => unresolved.push((name, value)),
+			}
+		}
+		defines = unresolved;
+
+		if defines.len() == before {
+			break;
+		}
+	}
+
+	for (_, value) in defines {
+		resolve(value, &symbols);
+	}
+
+	symbols
+}
@@ -0,0 +1,278 @@
+use super::memory::Workspace;
+use super::zeropage::ZeroPage;
+use crate::analysis::callgraph::CallGraph;
+use crate::analysis::cfg::Cfg;
+use crate::analysis::dataflow::liveness::{self, Liveness};
+use crate::analysis::dataflow::{self, BitSet};
+use crate::ir;
+use crate::message;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// What the first pass over the program learns about a function with a fixed frame.
+pub struct FrameUsage {
+	/// How many 2-byte slots the frame has.
+	pub slots: usize,
+	/// How heavily each slot is used. Uses inside loops count for more.
+	pub weights: Vec<u64>,
+}
+
+/// Give every temporary of a function a 2-byte slot in its frame.
+/// Temporaries can share a slot as long as one is never written while the other is still needed.
+/// Parameters get the first slots, in order, since the caller fills them all in before the call.
+pub fn assign_slots(function: &ir::Function, cfg: &Cfg) -> Vec<usize> {
+	let count = function.temps.len();
+	let results = dataflow::solve(&Liveness, function, cfg);
+
+	let mut interference = vec![BitSet::new(count); count];
+	let mut interfere = |a: usize, b: usize| {
+		if a != b {
+			interference[a].insert(b);
+			interference[b].insert(a);
+		}
+	};
+
+	for a in &function.params {
+		for b in &function.params {
+			interfere(a.0, b.0);
+		}
+	}
+
+	for (index, block) in function.blocks.iter().enumerate() {
+		let mut live = results.exit[index].clone();
+		liveness::read(block.terminator.operands(), &mut live);
+		for inst in block.insts.iter().rev() {
+			if let Some(dest) = inst.dest() {
+				for other in live.iter() {
+					interfere(dest.0, other);
+				}
+			}
+			liveness::step(inst, &mut live);
+		}
+	}
+
+	//Parameters are the first temporaries, so they get the first slots.
+	let mut slots: Vec<Option<usize>> = vec![None; count];
+	for temp in 0..count {
+		let taken: Vec<usize> = interference[temp]
+			.iter()
+			.filter_map(|other| slots[other])
+			.collect();
+		slots[temp] = (0..).find(|slot| !taken.contains(slot));
+	}
+	slots.into_iter().map(|slot| slot.unwrap()).collect()
+}
+
+/// Where each slot of a fixed frame ended up.
+pub struct FrameLayout {
+	slots: Vec<u16>,
+}
+
+impl FrameLayout {
+	/// Address of a byte in the frame, given its offset from the start of the frame.
+	pub fn address(&self, offset: u16) -> u16 {
+		self.slots[offset as usize / 2] + offset % 2
+	}
+}
+
+/// Where everything that isn't on the software stack goes.
+pub struct MemoryMap {
+	pub frames: HashMap<String, FrameLayout>,
+	pub workspace: Workspace,
+	pub stack_ptr: u16,
+	/// The lowest address the software stack can grow down to, just past the fixed frames in RAM.
+	pub stack_limit: u16,
+}
+
+/// Frames of functions that can't be in progress at the same time can share memory.
+/// A frame only has to avoid the frames of functions that might be in progress when it's called,
+/// so it goes just past the end of its deepest caller's frame. Total memory use is then the
+/// deepest chain of calls rather than the sum of every function.
+///
+/// Returns where each frame starts, and where the last one ends.
+/// Sizes can be in any unit, and functions with no size take up no space.
+fn overlay(calls: &CallGraph, sizes: &HashMap<String, usize>) -> (HashMap<String, usize>, usize) {
+	let mut bases: HashMap<String, usize> = HashMap::new();
+	let mut end = 0;
+
+	//Components are listed with callees first, so go backwards to place every caller before its callees.
+	for component in calls.components().iter().rev() {
+		//Only recursive functions share a component, and their frames are on the stack anyway.
+		let base = component
+			.iter()
+			.filter_map(|name| bases.get(name))
+			.copied()
+			.max()
+			.unwrap_or(0);
+
+		for name in component {
+			bases.insert(name.clone(), base);
+			let frame_end = base + sizes.get(name).copied().unwrap_or(0);
+			end = end.max(frame_end);
+
+			for callee in calls.callees(name) {
+				if !component.contains(callee) {
+					let callee_base = bases.entry(callee.clone()).or_insert(frame_end);
+					*callee_base = (*callee_base).max(frame_end);
+				}
+			}
+		}
+	}
+
+	(bases, end)
+}
+
+/// Decide where every fixed frame goes, along with the pointers and workspace the generated code needs.
+/// Zero page goes to the stack pointer first (since it has to be there), then the arithmetic workspace,
+/// then the most heavily used variables and temporaries. Everything else goes at the start of `ram`
+/// (inclusive), which also has to leave `stack_needed` bytes at the end for the software stack.
+/// Running out of zero page for the workspace or the variables that should be there is an error,
+/// unless `spill` lets them go in `ram` too.
+#[allow(clippy::too_many_arguments)]
+pub fn place(
+	calls: &CallGraph,
+	usage: &HashMap<String, FrameUsage>,
+	needs_stack: bool,
+	needs_workspace: bool,
+	reserved: &[(u8, u8)],
+	ram: (u16, u16),
+	stack_needed: u32,
+	spill: bool,
+) -> MemoryMap {
+	let mut zero_page = ZeroPage::new(reserved);
+	let mut ram_start = ram.0;
+
+	let mut stack_ptr = 0;
+	if needs_stack {
+		match zero_page.alloc(2) {
+			Some(address) => stack_ptr = address,
+			None => message::error(
+				"Not enough zero page for the stack pointer used by recursive functions (2 bytes are needed)".to_string(),
+				None,
+				None,
+			),
+		}
+	}
+
+	let mut workspace = Workspace::at(0);
+	if needs_workspace {
+		match zero_page.alloc(Workspace::SIZE) {
+			Some(address) => workspace = Workspace::at(address),
+			None => {
+				if !spill {
+					message::error(
+						format!(
+							"Not enough zero page for the arithmetic workspace ({} bytes are needed). Use `--zp-spill` to put it in slower memory instead",
+							Workspace::SIZE
+						),
+						None,
+						None,
+					);
+				}
+				workspace = Workspace::at(ram_start);
+				ram_start += Workspace::SIZE;
+			}
+		}
+	}
+
+	//Promote the busiest slots first, as long as the deepest chain of calls still fits.
+	let pairs = zero_page.alloc_pairs();
+	let mut candidates = vec![];
+	for (index, name) in calls.functions.iter().enumerate() {
+		if let Some(frame) = usage.get(name) {
+			for (slot, weight) in frame.weights.iter().enumerate() {
+				if *weight > 0 {
+					candidates.push((*weight, index, slot));
+				}
+			}
+		}
+	}
+	candidates.sort_by(|a, b| b.0.cmp(&a.0).then((a.1, a.2).cmp(&(b.1, b.2))));
+
+	let mut promoted: HashMap<String, BTreeSet<usize>> = HashMap::new();
+	let mut zp_sizes: HashMap<String, usize> = HashMap::new();
+	//By index, so the errors come out with the functions in order.
+	let mut spilled: BTreeMap<usize, usize> = BTreeMap::new();
+	for (_, index, slot) in candidates {
+		let name = &calls.functions[index];
+		*zp_sizes.entry(name.clone()).or_insert(0) += 1;
+		if overlay(calls, &zp_sizes).1 <= pairs.len() {
+			promoted.entry(name.clone()).or_default().insert(slot);
+		} else {
+			*zp_sizes.get_mut(name).unwrap() -= 1;
+			*spilled.entry(index).or_insert(0) += 1;
+		}
+	}
+	if !spill {
+		for (index, count) in spilled {
+			message::error(
+				format!(
+					"Not enough zero page for everything in function `{}`: {} of its variables and temporaries don't fit. Use `--zp-spill` to put them in slower memory instead",
+					calls.functions[index], count
+				),
+				None,
+				None,
+			);
+		}
+	}
+
+	//Everything else goes in regular memory.
+	let ram_sizes: HashMap<String, usize> = usage
+		.iter()
+		.map(|(name, frame)| {
+			let promoted = promoted.get(name).map_or(0, |slots| slots.len());
+			(name.clone(), frame.slots - promoted)
+		})
+		.collect();
+	let (zp_bases, _) = overlay(calls, &zp_sizes);
+	let (ram_bases, ram_end) = overlay(calls, &ram_sizes);
+
+	//Addresses are worked out in u32, so running off the end of memory can't wrap around.
+	let used = (ram_start - ram.0) as u32 + 2 * ram_end as u32;
+	let available = ram.1 as u32 + 1 - ram.0 as u32;
+	if used + stack_needed > available {
+		let stack = if stack_needed > 0 {
+			format!(
+				", along with at least {} bytes of software stack for recursive functions",
+				stack_needed
+			)
+		} else {
+			String::new()
+		};
+		message::error(
+			format!(
+				"Not enough RAM: variables need {} bytes from ${:04X}{}, but there are only {} bytes up to ${:04X}",
+				used, ram.0, stack, available, ram.1
+			),
+			None,
+			None,
+		);
+	}
+
+	let mut frames = HashMap::new();
+	for (name, frame) in usage {
+		let none = BTreeSet::new();
+		let promoted = promoted.get(name).unwrap_or(&none);
+		let mut next_zp = zp_bases[name];
+		let mut next_ram = ram_bases[name];
+		let mut slots = vec![];
+
+		for slot in 0..frame.slots {
+			if promoted.contains(&slot) {
+				slots.push(pairs[next_zp]);
+				next_zp += 1;
+			} else {
+				//If they don't fit, that's been reported, and the address only has to not overflow.
+				slots.push((ram_start as u32 + 2 * next_ram as u32) as u16);
+				next_ram += 1;
+			}
+		}
+		frames.insert(name.clone(), FrameLayout { slots });
+	}
+
+	MemoryMap {
+		frames,
+		workspace,
+		stack_ptr,
+		stack_limit: (ram.0 as u32 + used).min(0xFFFF) as u16,
+	}
+}
@@ -0,0 +1,394 @@
+use super::asm::Bytecode::*;
+use super::asm::Mode::*;
+use super::asm::Value;
+use super::memory::{Loc, HARDWARE_STACK_MARGIN, STACK_OVERFLOW, STACK_PTR};
+use super::ops;
+use super::ops::Flag;
+use super::registers::{self, Register, Step};
+use super::runtime::Routine;
+use super::Generator;
+use crate::ir::{
+	BinaryOp, BlockId, Comparison, Function, Inst, Operand, Temp, Terminator, UnaryOp,
+};
+use crate::target;
+use std::collections::HashSet;
+
+impl Generator<'_> {
+	pub fn function(&mut self, function: &Function) {
+		self.emit(Label(Generator::function_label(&function.name)));
+		self.locs = (0..function.temps.len())
+			.map(|temp| self.temp_loc(function, crate::ir::Temp(temp)))
+			.collect();
+		if self.is_stack_frame(function) {
+			self.check_stack();
+		}
+
+		//Only blocks that something jumps to need a label.
+		let targets: HashSet<BlockId> = function
+			.blocks
+			.iter()
+			.flat_map(|block| block.terminator.successors())
+			.collect();
+		let labels: Vec<String> = function.blocks.iter().map(|_| self.new_label()).collect();
+
+		for (index, block) in function.blocks.iter().enumerate() {
+			if targets.contains(&BlockId(index)) {
+				self.emit(Label(labels[index].clone()));
+			}
+			match self.tail_call(function, &block.insts, &block.terminator) {
+				Some((name, args)) => {
+					for inst in &block.insts[..block.insts.len() - 1] {
+						self.inst(function, inst);
+					}
+					self.store_counters(function, args);
+					let args: Vec<Loc> = args.iter().map(|arg| self.loc(*arg)).collect();
+					self.call(name, args, true);
+				}
+				None => {
+					for inst in &block.insts {
+						self.inst(function, inst);
+					}
+					self.terminator(function, &block.terminator, &labels, BlockId(index + 1));
+				}
+			}
+		}
+
+		if self.is_stack_frame(function) {
+			self.emit(Define(
+				Generator::frame_size_label(&function.name),
+				Value::Number(self.frame_size(function)),
+			));
+		}
+	}
+
+	fn inst(&mut self, function: &Function, inst: &Inst) {
+		//Loop counters are set and counted up in their register.
+		if let Some((counter, step)) = registers::counter_step(inst) {
+			if let Some(register) = self.counter(function, counter) {
+				self.count(register, self.locs[counter.0], step);
+				return;
+			}
+		}
+		self.store_counters(function, &inst.operands());
+
+		match inst {
+			Inst::Copy(dest, src) => {
+				let (dest, src) = (self.locs[dest.0], self.loc(*src));
+				ops::copy(self, dest, src);
+			}
+
+			Inst::Unary(dest, op, src) => {
+				let (dest, src) = (self.locs[dest.0], self.loc(*src));
+				match op {
+					UnaryOp::Neg => ops::neg(self, dest, src),
+					UnaryOp::Extend => ops::extend(self, dest, src),
+				}
+			}
+
+			Inst::Binary(dest_temp, op, a, b) => {
+				let (dest, a, b) = (self.locs[dest_temp.0], self.loc(*a), self.loc(*b));
+				match op {
+					BinaryOp::Add => ops::add(self, dest, a, b),
+					BinaryOp::Sub => ops::sub(self, dest, a, b),
+					BinaryOp::Mul => ops::mul(self, dest, a, b),
+					BinaryOp::Div => ops::div(self, dest, a, b),
+					BinaryOp::Rem => ops::rem(self, dest, a, b),
+					BinaryOp::ShiftLeft => ops::shift_left(self, dest, a, shift_count(b)),
+					BinaryOp::ShiftRight => ops::shift_right(self, dest, a, shift_count(b)),
+					BinaryOp::And => ops::and(self, dest, a, b),
+					BinaryOp::Compare(comparison) => {
+						let flag = ops::compare(self, *comparison, a, b);
+						let width = function.temps[dest_temp.0].width;
+						ops::materialize(self, dest, flag, width);
+					}
+				}
+			}
+
+			Inst::Call(dest, name, args) => {
+				let args: Vec<Loc> = args.iter().map(|arg| self.loc(*arg)).collect();
+				match name.as_str() {
+					"print" => self.print(args[0]),
+					"assert" => {
+						let flag = ops::test(self, args[0]);
+						self.assert(flag, &[], &args[1..]);
+					}
+					"assert_eq" => {
+						let flag = ops::compare(self, Comparison::Equal, args[0], args[1]);
+						self.assert(flag, &args[..2], &args[2..]);
+					}
+					_ => self.call(name, args, false),
+				}
+
+				//Return values come back in A (low byte) and X (high byte).
+				if let Some(dest) = dest {
+					let dest = self.locs[dest.0];
+					ops::store_ax(self, dest);
+				}
+			}
+		}
+	}
+
+	fn terminator(
+		&mut self,
+		function: &Function,
+		terminator: &Terminator,
+		labels: &[String],
+		next: BlockId,
+	) {
+		match terminator {
+			Terminator::Jump(target) => {
+				if *target != next {
+					self.emit(JMP(Absolute(Value::symbol(&labels[target.0]))));
+				}
+			}
+
+			Terminator::Branch(comparison, a, b, when_true, when_false) => {
+				let flag = self.condition(function, *comparison, *a, *b);
+				if *when_true == next {
+					ops::jump_if(self, flag.negate(), &labels[when_false.0]);
+				} else {
+					ops::jump_if(self, flag, &labels[when_true.0]);
+					if *when_false != next {
+						self.emit(JMP(Absolute(Value::symbol(&labels[when_false.0]))));
+					}
+				}
+			}
+
+			Terminator::Return(value) => {
+				if let Some(value) = value {
+					self.store_counters(function, &[*value]);
+					let value = self.loc(*value);
+					ops::load_ax(self, value);
+				}
+				self.emit(RTS(Implied));
+			}
+		}
+	}
+
+	//Set the flags according to a comparison, returning the flag state that means it holds.
+	fn condition(
+		&mut self,
+		function: &Function,
+		comparison: Comparison,
+		a: Operand,
+		b: Operand,
+	) -> Flag {
+		//A loop counter is from 0 to 255, so it can be compared with a constant in that range
+		//straight from its register. `a <= b` is `a < b + 1`, and `a > b` is `a >= b + 1`.
+		if let (Operand::Temp(temp), Operand::Const(limit)) = (a, b) {
+			if let Some(register) = self.counter(function, temp) {
+				let limit = limit as i32;
+				let (limit, flag) = match comparison {
+					Comparison::Less => (limit, Flag::NotCarry),
+					Comparison::LessOrEqual => (limit + 1, Flag::NotCarry),
+					Comparison::Greater => (limit + 1, Flag::Carry),
+					Comparison::GreaterOrEqual => (limit, Flag::Carry),
+					Comparison::Equal => (limit, Flag::Zero),
+					Comparison::NotEqual => (limit, Flag::NotZero),
+				};
+				if (0..=255).contains(&limit) {
+					let limit = Immediate(Value::Number(limit as u16));
+					self.emit(match register {
+						Register::X => CPX(limit),
+						Register::Y => CPY(limit),
+					});
+					return flag;
+				}
+			}
+		}
+
+		self.store_counters(function, &[a, b]);
+		let (a, b) = (self.loc(a), self.loc(b));
+		match (comparison, b) {
+			//Testing against zero doesn't need a full comparison.
+			(Comparison::NotEqual, Loc::Const(0)) => ops::test(self, a),
+			(Comparison::Equal, Loc::Const(0)) => ops::test(self, a).negate(),
+			_ => ops::compare(self, comparison, a, b),
+		}
+	}
+
+	//Set a loop counter, or count it up. When it's set, the high byte in its frame is cleared,
+	//so copying the low byte there is enough to give the frame its value.
+	fn count(&mut self, register: Register, loc: Loc, step: Step) {
+		match (step, register) {
+			(Step::Start(value), _) => {
+				let value = Immediate(Value::Number(value as u16));
+				self.emit_load(match register {
+					Register::X => LDX(value),
+					Register::Y => LDY(value),
+				});
+				self.emit_load(LDA(Immediate(Value::Number(0))));
+				self.emit_op(STA, loc, 1);
+			}
+			(Step::Increment, Register::X) => self.emit(INX(Implied)),
+			(Step::Increment, Register::Y) => self.emit(INY(Implied)),
+		}
+	}
+
+	//Copy any loop counters among some operands from their registers to their frames, so they
+	//can be read like anything else.
+	fn store_counters(&mut self, function: &Function, operands: &[Operand]) {
+		for operand in operands {
+			let temp: Temp = match operand {
+				Operand::Temp(temp) => *temp,
+				Operand::Const(_) => continue,
+			};
+			let store = match self.counter(function, temp) {
+				Some(Register::X) => STX(self.locs[temp.0].byte(0)),
+				Some(Register::Y) => STY(self.locs[temp.0].byte(0)),
+				None => continue,
+			};
+			if !(self.optimize && self.tracker.redundant_store(&store)) {
+				self.emit(store);
+			}
+		}
+	}
+
+	//The number goes in the arithmetic workspace, which the runtime divides down into digits.
+	fn print(&mut self, value: Loc) {
+		let workspace = self.workspace;
+		ops::copy(self, workspace.lhs, value);
+		self.call_runtime(Routine::Print);
+	}
+
+	//If an assertion doesn't hold, leave where it is in the source (and the values that were
+	//compared, for `assert_eq`) for whoever is running the program, if the target keeps them,
+	//then let the target deal with it.
+	fn assert(&mut self, holds: Flag, values: &[Loc], span: &[Loc]) {
+		//The failure code is short enough to branch over.
+		let pass = self.new_label();
+		self.emit(holds.branch(&pass));
+
+		if self.target.assert_details.is_some() {
+			for (index, value) in span.iter().chain(values).enumerate() {
+				for n in 0..2 {
+					self.emit_op(LDA, *value, n);
+					let offset = 2 * index as i32 + n as i32;
+					self.emit(STA(Absolute(
+						Value::symbol(target::ASSERT_DETAILS).offset(offset),
+					)));
+				}
+			}
+		}
+
+		let kind = if values.is_empty() { 1 } else { 2 };
+		self.emit(LDA(Immediate(Value::Number(kind))));
+		self.emit(JMP(Absolute(Value::symbol(target::ASSERT_FAILED))));
+		self.emit(Label(pass));
+	}
+
+	//A recursive function can be called more times than there's room for, either for its return
+	//address on the hardware stack, or for its frame on the software stack (which the caller has
+	//already made room for). Since that only ever happens by mistake, give up straight away.
+	fn check_stack(&mut self) {
+		let overflow = self.new_label();
+		let fits = self.new_label();
+		self.emit(TSX(Implied));
+		self.emit(CPX(Immediate(Value::Number(HARDWARE_STACK_MARGIN))));
+		self.emit(BCC(Relative(Value::symbol(&overflow))));
+
+		let limit = Value::Number(self.memory_map.stack_limit);
+		self.emit(LDA(ZeroPage(Value::symbol(STACK_PTR))));
+		self.emit(CMP(Immediate(limit.clone().low())));
+		self.emit(LDA(ZeroPage(Value::symbol(STACK_PTR).offset(1))));
+		self.emit(SBC(Immediate(limit.high())));
+		self.emit(BCS(Relative(Value::symbol(&fits))));
+		self.emit(Label(overflow));
+		self.emit(JMP(Absolute(Value::symbol(STACK_OVERFLOW))));
+		self.emit(Label(fits));
+	}
+
+	//If a block ends by returning what a call returns, the callee can return straight to our
+	//caller instead, so the call can be a jump. This only works if the callee has a fixed frame,
+	//since a stack frame has to be freed after the call.
+	fn tail_call<'f>(
+		&self,
+		function: &Function,
+		insts: &'f [Inst],
+		terminator: &Terminator,
+	) -> Option<(&'f str, &'f [Operand])> {
+		if !self.optimize {
+			return None;
+		}
+		let (dest, name, args) = match insts.last() {
+			Some(Inst::Call(dest, name, args)) => (dest, name, args),
+			_ => return None,
+		};
+		let callee = self.module.function(name)?;
+		if self.is_stack_frame(callee) {
+			return None;
+		}
+
+		let returned = match terminator {
+			Terminator::Return(value) => *value,
+			_ => return None,
+		};
+		let forwarded = match (returned, dest) {
+			(Some(Operand::Temp(value)), Some(dest)) => value == *dest,
+			//Nothing is returned, so it doesn't matter what the callee leaves in A and X.
+			(None, _) => !function.returns_value,
+			_ => false,
+		};
+		if forwarded {
+			Some((name, args))
+		} else {
+			None
+		}
+	}
+
+	//Call a user-defined function. Arguments have all been evaluated already.
+	//A tail call jumps to the function, so it returns straight to our caller.
+	fn call(&mut self, name: &str, args: Vec<Loc>, tail: bool) {
+		let module = self.module;
+		let callee = module.function(name).unwrap();
+		let params: Vec<Loc> = callee
+			.params
+			.iter()
+			.map(|param| self.temp_loc(callee, *param))
+			.collect();
+		let label = Value::symbol(&Generator::function_label(name));
+
+		if self.is_stack_frame(callee) {
+			if tail {
+				panic!("COMPILER BUG: Tail call to a function with a stack frame");
+			}
+
+			//Make room for the callee's frame. The frame pointer moves down,
+			//so our own stack values are temporarily further away from it.
+			let size = Value::symbol(&Generator::frame_size_label(name));
+			ops::stack_alloc(self, size.clone());
+			for (param, arg) in params.into_iter().zip(args) {
+				for n in 0..2 {
+					match arg.index(n) {
+						Some(offset) => {
+							let index = size.clone().offset(offset as i32);
+							self.emit(LDY(Immediate(index)));
+							self.emit(LDA(arg.byte(n)));
+						}
+						None => self.emit(LDA(arg.byte(n))),
+					}
+					self.emit_op(STA, param, n);
+				}
+			}
+			self.emit(JSR(Absolute(label)));
+			ops::stack_free(self, size);
+		} else {
+			for (param, arg) in params.into_iter().zip(args) {
+				ops::copy(self, param, arg);
+			}
+			if tail {
+				self.emit(JMP(Absolute(label)));
+			} else {
+				self.emit(JSR(Absolute(label)));
+			}
+		}
+	}
+}
+
+//Shifts are only ever by a constant number of bits.
+fn shift_count(count: Loc) -> u16 {
+	match count {
+		Loc::Const(count @ 0..=15) => count as u16,
+		_ => panic!("COMPILER BUG: Shift by something other than a constant from 0 to 15"),
+	}
+}
@@ -0,0 +1,80 @@
+use super::asm::Mode;
+use super::asm::Mode::*;
+use super::asm::Value;
+use super::asm::Value::*;
+use super::registers::Register;
+
+/// Symbol for the zero page pointer to the stack frame of the innermost call to a recursive function.
+/// The software stack grows down from the end of the target's RAM.
+pub const STACK_PTR: &str = "STACK_PTR";
+/// Label of the code that runs when a call to a recursive function finds there's no stack left.
+pub const STACK_OVERFLOW: &str = "STACK_overflow";
+/// How much of the hardware stack has to be left when a recursive function starts, for the return
+/// addresses of whatever it calls in turn, and the runtime routines.
+pub const HARDWARE_STACK_MARGIN: u16 = 32;
+
+/// Where a 16-bit value lives.
+#[derive(Clone, Copy)]
+pub enum Loc {
+	/// A value known at compile time.
+	Const(i16),
+	/// A little-endian value at a fixed address.
+	Mem(u16),
+	/// A little-endian value at an offset into the current stack frame.
+	Stack(u16),
+	/// A single byte in an index register.
+	Reg(Register),
+}
+
+impl Loc {
+	/// Operand for reading byte `n` (0 = low, 1 = high) of this value.
+	pub fn byte(&self, n: u16) -> Mode {
+		match self {
+			Loc::Const(value) => Immediate(Number((*value as u16 >> (8 * n)) & 0xFF)),
+			Loc::Mem(address) => {
+				let address = address + n;
+				if address < 0x100 {
+					ZeroPage(Number(address))
+				} else {
+					Absolute(Number(address))
+				}
+			}
+			//Y has to hold the offset, see `index()`.
+			Loc::Stack(_) => IndirectY(Value::symbol(STACK_PTR)),
+			Loc::Reg(_) => panic!("COMPILER BUG: A register can't be used as an operand"),
+		}
+	}
+
+	/// What the Y register must hold for `byte(n)` to work, if anything.
+	pub fn index(&self, n: u16) -> Option<u16> {
+		match self {
+			Loc::Stack(offset) => Some(offset + n),
+			_ => None,
+		}
+	}
+}
+
+/// Where the runtime math routines take their operands and leave their results.
+#[derive(Clone, Copy)]
+pub struct Workspace {
+	pub lhs: Loc,
+	pub rhs: Loc,
+	pub result: Loc,
+	pub rem: Loc,
+	/// Only one byte.
+	pub sign: Loc,
+}
+
+impl Workspace {
+	pub const SIZE: u16 = 9;
+
+	pub fn at(address: u16) -> Workspace {
+		Workspace {
+			lhs: Loc::Mem(address),
+			rhs: Loc::Mem(address + 2),
+			result: Loc::Mem(address + 4),
+			rem: Loc::Mem(address + 6),
+			sign: Loc::Mem(address + 8),
+		}
+	}
+}
@@ -0,0 +1,322 @@
+use super::asm::Bytecode;
+use super::asm::Bytecode::*;
+use super::asm::Mode;
+use super::asm::Mode::*;
+use super::asm::Value;
+use super::asm::Value::*;
+use super::memory::{Loc, STACK_PTR};
+use super::runtime::Routine;
+use super::Generator;
+use crate::ir::{Comparison, Width};
+
+//Helpers for emitting 16-bit two's complement operations.
+//All of them work one byte at a time through the accumulator, low byte first,
+//so that the carry flag chains from the low byte into the high byte.
+//Values on the stack also need the Y register, which doesn't disturb the carry.
+
+/// dest = src
+pub fn copy(gen: &mut Generator, dest: Loc, src: Loc) {
+	for n in 0..2 {
+		gen.emit_op(LDA, src, n);
+		gen.emit_op(STA, dest, n);
+	}
+}
+
+/// Load a value into A (low byte) and X (high byte).
+pub fn load_ax(gen: &mut Generator, src: Loc) {
+	if src.index(0).is_some() {
+		//There's no `ldx (zp),y`.
+		gen.emit_op(LDA, src, 1);
+		gen.emit(TAX(Implied));
+		gen.emit_op(LDA, src, 0);
+	} else {
+		gen.emit(LDA(src.byte(0)));
+		gen.emit(LDX(src.byte(1)));
+	}
+}
+
+/// Store A (low byte) and X (high byte) into a value.
+pub fn store_ax(gen: &mut Generator, dest: Loc) {
+	if dest.index(0).is_some() {
+		gen.emit_op(STA, dest, 0);
+		gen.emit(TXA(Implied));
+		gen.emit(INY(Implied));
+		gen.emit(STA(dest.byte(1)));
+	} else {
+		gen.emit(STA(dest.byte(0)));
+		gen.emit(STX(dest.byte(1)));
+	}
+}
+
+/// Reserve `size` bytes on the software stack.
+pub fn stack_alloc(gen: &mut Generator, size: Value) {
+	gen.emit(SEC(Implied));
+	for n in 0..2 {
+		let size = if n == 0 {
+			size.clone().low()
+		} else {
+			size.clone().high()
+		};
+		gen.emit(LDA(ZeroPage(Value::symbol(STACK_PTR).offset(n))));
+		gen.emit(SBC(Immediate(size)));
+		gen.emit(STA(ZeroPage(Value::symbol(STACK_PTR).offset(n))));
+	}
+}
+
+/// Release `size` bytes from the software stack. Preserves A and X.
+pub fn stack_free(gen: &mut Generator, size: Value) {
+	gen.emit(PHA(Implied));
+	gen.emit(CLC(Implied));
+	for n in 0..2 {
+		let size = if n == 0 {
+			size.clone().low()
+		} else {
+			size.clone().high()
+		};
+		gen.emit(LDA(ZeroPage(Value::symbol(STACK_PTR).offset(n))));
+		gen.emit(ADC(Immediate(size)));
+		gen.emit(STA(ZeroPage(Value::symbol(STACK_PTR).offset(n))));
+	}
+	gen.emit(PLA(Implied));
+}
+
+/// dest = a + b
+pub fn add(gen: &mut Generator, dest: Loc, a: Loc, b: Loc) {
+	gen.emit(CLC(Implied));
+	for n in 0..2 {
+		gen.emit_op(LDA, a, n);
+		gen.emit_op(ADC, b, n);
+		gen.emit_op(STA, dest, n);
+	}
+}
+
+/// dest = a - b
+pub fn sub(gen: &mut Generator, dest: Loc, a: Loc, b: Loc) {
+	gen.emit(SEC(Implied));
+	for n in 0..2 {
+		gen.emit_op(LDA, a, n);
+		gen.emit_op(SBC, b, n);
+		gen.emit_op(STA, dest, n);
+	}
+}
+
+/// dest = -src
+pub fn neg(gen: &mut Generator, dest: Loc, src: Loc) {
+	sub(gen, dest, Loc::Const(0), src);
+}
+
+/// dest = src, where src is a single byte that's zero or one.
+pub fn extend(gen: &mut Generator, dest: Loc, src: Loc) {
+	gen.emit_op(LDA, src, 0);
+	gen.emit_op(STA, dest, 0);
+	gen.emit(LDA(Immediate(Number(0))));
+	gen.emit_op(STA, dest, 1);
+}
+
+/// dest = a << count
+pub fn shift_left(gen: &mut Generator, dest: Loc, a: Loc, mut count: u16) {
+	//A whole byte at a time is just a move.
+	if count >= 8 {
+		gen.emit_op(LDA, a, 0);
+		gen.emit_op(STA, dest, 1);
+		gen.emit(LDA(Immediate(Number(0))));
+		gen.emit_op(STA, dest, 0);
+		count -= 8;
+	} else {
+		copy(gen, dest, a);
+	}
+
+	for _ in 0..count {
+		shift_byte(gen, ASL, dest, 0);
+		shift_byte(gen, ROL, dest, 1);
+	}
+}
+
+/// dest = a >> count, keeping the sign.
+pub fn shift_right(gen: &mut Generator, dest: Loc, a: Loc, mut count: u16) {
+	if count >= 8 {
+		gen.emit_op(LDA, a, 1);
+		gen.emit_op(STA, dest, 0);
+		//Fill the high byte with copies of the sign bit: $FF if the carry is set, otherwise 0.
+		gen.emit(ASL(Accumulator));
+		gen.emit(LDA(Immediate(Number(0))));
+		gen.emit(ADC(Immediate(Number(0xFF))));
+		gen.emit(EOR(Immediate(Number(0xFF))));
+		gen.emit_op(STA, dest, 1);
+		count -= 8;
+	} else {
+		copy(gen, dest, a);
+	}
+
+	for _ in 0..count {
+		//The carry gets the sign bit, which then goes back in at the top.
+		gen.emit_op(LDA, dest, 1);
+		gen.emit(CMP(Immediate(Number(0x80))));
+		gen.emit(ROR(Accumulator));
+		gen.emit_op(STA, dest, 1);
+		shift_byte(gen, ROR, dest, 0);
+	}
+}
+
+//Shift or rotate one byte of a value in place. Memory can be shifted directly, but the stack has
+//to go through the accumulator. Neither way changes the carry until the shift itself.
+fn shift_byte(gen: &mut Generator, op: fn(Mode) -> Bytecode, dest: Loc, n: u16) {
+	match dest.index(n) {
+		None => gen.emit(op(dest.byte(n))),
+		Some(_) => {
+			gen.emit_op(LDA, dest, n);
+			gen.emit(op(Accumulator));
+			gen.emit_op(STA, dest, n);
+		}
+	}
+}
+
+/// dest = a & b
+pub fn and(gen: &mut Generator, dest: Loc, a: Loc, b: Loc) {
+	for n in 0..2 {
+		gen.emit_op(LDA, a, n);
+		gen.emit_op(AND, b, n);
+		gen.emit_op(STA, dest, n);
+	}
+}
+
+/// dest = a * b
+pub fn mul(gen: &mut Generator, dest: Loc, a: Loc, b: Loc) {
+	let workspace = gen.workspace;
+	copy(gen, workspace.lhs, a);
+	copy(gen, workspace.rhs, b);
+	gen.call_runtime(Routine::Multiply);
+	copy(gen, dest, workspace.result);
+}
+
+/// dest = a / b
+pub fn div(gen: &mut Generator, dest: Loc, a: Loc, b: Loc) {
+	let workspace = gen.workspace;
+	copy(gen, workspace.lhs, a);
+	copy(gen, workspace.rhs, b);
+	gen.call_runtime(Routine::Divide);
+	copy(gen, dest, workspace.lhs);
+}
+
+/// dest = a % b
+pub fn rem(gen: &mut Generator, dest: Loc, a: Loc, b: Loc) {
+	let workspace = gen.workspace;
+	copy(gen, workspace.lhs, a);
+	copy(gen, workspace.rhs, b);
+	gen.call_runtime(Routine::Divide);
+	copy(gen, dest, workspace.rem);
+}
+
+/// A processor flag state that a comparison can leave behind.
+#[derive(Clone, Copy)]
+pub enum Flag {
+	Zero,
+	NotZero,
+	Negative,
+	NotNegative,
+	Carry,
+	NotCarry,
+}
+
+impl Flag {
+	pub fn negate(self) -> Flag {
+		match self {
+			Flag::Zero => Flag::NotZero,
+			Flag::NotZero => Flag::Zero,
+			Flag::Negative => Flag::NotNegative,
+			Flag::NotNegative => Flag::Negative,
+			Flag::Carry => Flag::NotCarry,
+			Flag::NotCarry => Flag::Carry,
+		}
+	}
+
+	/// Branch instruction that's taken when this flag state holds.
+	pub fn branch(self, target: &str) -> Bytecode {
+		let target = Relative(Value::symbol(target));
+		match self {
+			Flag::Zero => BEQ(target),
+			Flag::NotZero => BNE(target),
+			Flag::Negative => BMI(target),
+			Flag::NotNegative => BPL(target),
+			Flag::Carry => BCS(target),
+			Flag::NotCarry => BCC(target),
+		}
+	}
+}
+
+/// Compare two signed values, returning the flag state that means the comparison is true.
+pub fn compare(gen: &mut Generator, comparison: Comparison, a: Loc, b: Loc) -> Flag {
+	match comparison {
+		//a > b is the same as b < a, and a <= b is the same as b >= a.
+		Comparison::Greater => compare(gen, Comparison::Less, b, a),
+		Comparison::LessOrEqual => compare(gen, Comparison::GreaterOrEqual, b, a),
+
+		Comparison::Less | Comparison::GreaterOrEqual => {
+			//Subtract without storing the result. For signed values, a < b exactly when
+			//the sign of the difference is wrong (N) unless the subtraction overflowed (V).
+			let label = gen.new_label();
+			gen.emit_op(LDA, a, 0);
+			gen.emit_op(CMP, b, 0);
+			gen.emit_op(LDA, a, 1);
+			gen.emit_op(SBC, b, 1);
+			gen.emit(BVC(Relative(Value::symbol(&label))));
+			gen.emit(EOR(Immediate(Number(0x80))));
+			gen.emit(Label(label));
+
+			match comparison {
+				Comparison::Less => Flag::Negative,
+				_ => Flag::NotNegative,
+			}
+		}
+
+		Comparison::Equal | Comparison::NotEqual => {
+			//If the low bytes differ, Z is already clear.
+			let label = gen.new_label();
+			gen.emit_op(LDA, a, 0);
+			gen.emit_op(CMP, b, 0);
+			gen.emit(BNE(Relative(Value::symbol(&label))));
+			gen.emit_op(LDA, a, 1);
+			gen.emit_op(CMP, b, 1);
+			gen.emit(Label(label));
+
+			match comparison {
+				Comparison::Equal => Flag::Zero,
+				_ => Flag::NotZero,
+			}
+		}
+	}
+}
+
+/// Set the flags according to whether a value is nonzero (true) or zero (false).
+pub fn test(gen: &mut Generator, src: Loc) -> Flag {
+	gen.emit_op(LDA, src, 0);
+	gen.emit_op(ORA, src, 1);
+	Flag::NotZero
+}
+
+/// Jump to the target if the flag state holds.
+/// Relative branches only reach 127 bytes, so this branches over an absolute jump instead.
+pub fn jump_if(gen: &mut Generator, flag: Flag, target: &str) {
+	let skip = gen.new_label();
+	gen.emit(flag.negate().branch(&skip));
+	gen.emit(JMP(Absolute(Value::symbol(target))));
+	gen.emit(Label(skip));
+}
+
+/// Turn a flag state into a value: dest = 1 if it holds, 0 otherwise.
+/// Must be called right after the flags are set, since loads would overwrite them.
+pub fn materialize(gen: &mut Generator, dest: Loc, flag: Flag, width: Width) {
+	let is_true = gen.new_label();
+	let store = gen.new_label();
+	gen.emit(flag.branch(&is_true));
+	gen.emit(LDA(Immediate(Number(0))));
+	gen.emit(BEQ(Relative(Value::symbol(&store))));
+	gen.emit(Label(is_true));
+	gen.emit(LDA(Immediate(Number(1))));
+	gen.emit(Label(store));
+	gen.emit_op(STA, dest, 0);
+	if width == Width::Word {
+		gen.emit(LDA(Immediate(Number(0))));
+		gen.emit_op(STA, dest, 1);
+	}
+}
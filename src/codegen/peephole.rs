@@ -0,0 +1,537 @@
+use super::asm::Bytecode::{self, *};
+use super::asm::Mode::{self, *};
+use super::asm::Value;
+use super::memory::STACK_PTR;
+use crate::flags::Options;
+use std::collections::HashMap;
+
+//A peephole optimizer over generated code. Each rule looks at the code at one position, and if it
+//recognises what's there, gives a shorter replacement. Rules only ever remove bytes, so a branch
+//that's in range before a pass is still in range after it.
+//
+//Named symbols might be hardware registers, where every read and write matters. So rules that
+//remove memory accesses only touch memory the compiler allocated itself, which is always given
+//as a number (or reached through the stack pointer).
+
+/// How many items a rule replaces, and what it replaces them with.
+pub type Rewrite = (usize, Vec<Bytecode>);
+
+/// A rule gets the code, where everything is, and the position to look at.
+pub type Rule = fn(&[Bytecode], &Layout, usize) -> Option<Rewrite>;
+
+/// Every rule, along with its name (for `--no-peephole`) and the optimization level that enables it.
+pub const RULES: [(&str, Rule, u8); 6] = [
+	("redundant-load", redundant_load, 1),
+	("redundant-store", redundant_store, 1),
+	("jump-to-next", jump_to_next, 1),
+	("branch-over-jump", branch_over_jump, 1),
+	("unreachable-code", unreachable_code, 1),
+	("dead-store", dead_store, 2),
+];
+
+/// Apply every rule enabled at the optimization level, and not turned off by name, until none of
+/// them match anywhere.
+pub fn optimize(code: Vec<Bytecode>, options: &Options) -> Vec<Bytecode> {
+	let rules: Vec<Rule> = RULES
+		.iter()
+		.filter(|(name, _, enabled)| {
+			*enabled <= options.opt_level && !options.no_peephole.iter().any(|off| off == name)
+		})
+		.map(|(_, rule, _)| *rule)
+		.collect();
+	run(code, &rules)
+}
+
+//Apply some rules until none of them match anywhere.
+fn run(mut code: Vec<Bytecode>, rules: &[Rule]) -> Vec<Bytecode> {
+	loop {
+		let layout = Layout::new(&code);
+		let mut result = Vec::with_capacity(code.len());
+		let mut changed = false;
+		let mut at = 0;
+
+		'scan: while at < code.len() {
+			for rule in rules {
+				if let Some((count, replacement)) = rule(&code, &layout, at) {
+					result.extend(replacement);
+					at += count;
+					changed = true;
+					continue 'scan;
+				}
+			}
+			result.push(code[at].clone());
+			at += 1;
+		}
+
+		code = result;
+		if !changed {
+			return code;
+		}
+	}
+}
+
+/// Where each item of the code would be assembled, relative to the start.
+pub struct Layout {
+	addresses: Vec<u16>,
+	labels: HashMap<String, usize>,
+}
+
+impl Layout {
+	pub fn new(code: &[Bytecode]) -> Layout {
+		let mut layout = Layout {
+			addresses: vec![],
+			labels: HashMap::new(),
+		};
+
+		let mut address: u16 = 0;
+		for (index, item) in code.iter().enumerate() {
+			layout.addresses.push(address);
+			if let Label(name) = item {
+				layout.labels.insert(name.clone(), index);
+			}
+			address = address.wrapping_add(item.size(address));
+		}
+		layout
+	}
+}
+
+//Memory that only the compiler uses, so reads and writes to it can be removed safely.
+fn is_compiler_memory(mode: &Mode) -> bool {
+	match mode {
+		ZeroPage(Value::Number(_)) | Absolute(Value::Number(_)) => true,
+		IndirectY(Value::Symbol(pointer, 0)) => pointer == STACK_PTR,
+		_ => false,
+	}
+}
+
+//The address of memory accessed directly, without indexing.
+fn direct_address(mode: &Mode) -> Option<u16> {
+	match mode {
+		ZeroPage(Value::Number(address)) | Absolute(Value::Number(address)) => Some(*address),
+		_ => None,
+	}
+}
+
+//Whether nothing reads the N and Z flags as they are just before the given position.
+fn flags_unused(code: &[Bytecode], at: usize) -> bool {
+	for item in &code[at..] {
+		if branch_target(item).is_some() {
+			return false;
+		}
+		match item {
+			BEQ(_) | BNE(_) | BMI(_) | BPL(_) | PHP(_) | JMP(_) => return false,
+			LDA(_) | LDX(_) | LDY(_) | ADC(_) | SBC(_) | AND(_) | ORA(_) | EOR(_) | CMP(_)
+			| CPX(_) | CPY(_) | INX(_) | INY(_) | DEX(_) | DEY(_) | INC(_) | DEC(_) | TAX(_)
+			| TAY(_) | TXA(_) | TYA(_) | TSX(_) | PLA(_) | PLP(_) | ASL(_) | LSR(_) | ROL(_)
+			| ROR(_) | BIT(_) => return true,
+			//Nothing expects flags to be passed in or out of a subroutine.
+			JSR(_) | RTS(_) | RTI(_) => return true,
+			_ => {}
+		}
+	}
+	true
+}
+
+fn branch_target(item: &Bytecode) -> Option<&str> {
+	match item {
+		BCC(Relative(Value::Symbol(label, 0)))
+		| BCS(Relative(Value::Symbol(label, 0)))
+		| BEQ(Relative(Value::Symbol(label, 0)))
+		| BNE(Relative(Value::Symbol(label, 0)))
+		| BMI(Relative(Value::Symbol(label, 0)))
+		| BPL(Relative(Value::Symbol(label, 0)))
+		| BVC(Relative(Value::Symbol(label, 0)))
+		| BVS(Relative(Value::Symbol(label, 0))) => Some(label),
+		_ => None,
+	}
+}
+
+//The branch that's taken exactly when the given one isn't.
+fn inverse_branch(item: &Bytecode, target: Mode) -> Option<Bytecode> {
+	let inverse = match item {
+		BCC(_) => BCS(target),
+		BCS(_) => BCC(target),
+		BEQ(_) => BNE(target),
+		BNE(_) => BEQ(target),
+		BMI(_) => BPL(target),
+		BPL(_) => BMI(target),
+		BVC(_) => BVS(target),
+		BVS(_) => BVC(target),
+		_ => return None,
+	};
+	Some(inverse)
+}
+
+/// `sta x; lda x` leaves the value in A already, so the load can go.
+/// Also for X and Y.
+pub fn redundant_load(code: &[Bytecode], _layout: &Layout, at: usize) -> Option<Rewrite> {
+	let (store, load) = (code.get(at)?, code.get(at + 1)?);
+	let same = match (store, load) {
+		(STA(a), LDA(b)) | (STX(a), LDX(b)) | (STY(a), LDY(b)) => a == b && is_compiler_memory(a),
+		_ => false,
+	};
+
+	//The load also sets the flags, so make sure nothing was relying on that.
+	if same && flags_unused(code, at + 2) {
+		return Some((2, vec![store.clone()]));
+	}
+	None
+}
+
+/// `lda x; sta x` stores what's already there, so the store can go.
+/// Also for X and Y.
+pub fn redundant_store(code: &[Bytecode], _layout: &Layout, at: usize) -> Option<Rewrite> {
+	let (load, store) = (code.get(at)?, code.get(at + 1)?);
+	match (load, store) {
+		(LDA(a), STA(b)) | (LDX(a), STX(b)) | (LDY(a), STY(b))
+			if a == b && is_compiler_memory(a) =>
+		{
+			Some((2, vec![load.clone()]))
+		}
+		_ => None,
+	}
+}
+
+/// A jump to a label that comes straight after it does nothing.
+pub fn jump_to_next(code: &[Bytecode], _layout: &Layout, at: usize) -> Option<Rewrite> {
+	let target = match code.get(at)? {
+		JMP(Absolute(Value::Symbol(label, 0))) => label,
+		_ => return None,
+	};
+
+	for item in &code[at + 1..] {
+		match item {
+			Label(label) if label == target => return Some((1, vec![])),
+			Label(_) | Define(_, _) => {}
+			_ => return None,
+		}
+	}
+	None
+}
+
+/// Branching over a jump is how a conditional jump gets past the 127 byte limit of a branch.
+/// When the target turns out to be close enough, a single branch the other way will do.
+pub fn branch_over_jump(code: &[Bytecode], layout: &Layout, at: usize) -> Option<Rewrite> {
+	let branch = code.get(at)?;
+	let skip = branch_target(branch)?;
+	let target = match (code.get(at + 1)?, code.get(at + 2)?) {
+		(JMP(Absolute(Value::Symbol(target, 0))), Label(label)) if label == skip => target,
+		_ => return None,
+	};
+
+	//Padding could change size as the code moves, so don't guess across it.
+	let target_index = *layout.labels.get(target)?;
+	let (first, last) = (at.min(target_index), at.max(target_index));
+	if code[first..last]
+		.iter()
+		.any(|item| matches!(item, Align(_) | Org(_)))
+	{
+		return None;
+	}
+
+	let from = layout.addresses[at] as i32 + 2;
+	let distance = layout.addresses[target_index] as i32 - from;
+	if !(-128..=127).contains(&distance) {
+		return None;
+	}
+
+	let inverse = inverse_branch(branch, Relative(Value::symbol(target)))?;
+	Some((3, vec![inverse, code[at + 2].clone()]))
+}
+
+/// Nothing after a jump or return can run, up until the next label.
+pub fn unreachable_code(code: &[Bytecode], _layout: &Layout, at: usize) -> Option<Rewrite> {
+	match code.get(at)? {
+		JMP(_) | RTS(_) | RTI(_) => {}
+		_ => return None,
+	}
+
+	let mut kept = vec![code[at].clone()];
+	let mut count = 1;
+	for item in &code[at + 1..] {
+		match item {
+			Label(_) => break,
+			//Directives might be data that something else refers to.
+			_ if item.mnemonic().is_none() => kept.push(item.clone()),
+			_ => {}
+		}
+		count += 1;
+	}
+
+	if kept.len() == count {
+		return None;
+	}
+	Some((count, kept))
+}
+
+/// A store is dead if the same memory is stored to again before anything could read it.
+/// Only straight-line code is checked, since anything could happen after a branch or call.
+pub fn dead_store(code: &[Bytecode], _layout: &Layout, at: usize) -> Option<Rewrite> {
+	let address = match code.get(at)? {
+		STA(mode) | STX(mode) | STY(mode) => direct_address(mode)?,
+		_ => return None,
+	};
+
+	for item in &code[at + 1..] {
+		let mode = match item {
+			Label(_) => return None,
+			_ if branch_target(item).is_some() => return None,
+			JMP(_) | JSR(_) | RTS(_) | RTI(_) | BRK(_) => return None,
+			_ => match item.mode() {
+				Some(mode) => mode,
+				None => continue,
+			},
+		};
+
+		if let STA(_) | STX(_) | STY(_) = item {
+			if direct_address(mode) == Some(address) {
+				return Some((1, vec![]));
+			}
+		}
+
+		//Indexed and indirect accesses could be to anywhere.
+		match mode {
+			Implied | Accumulator | Immediate(_) => {}
+			ZeroPage(_) | Absolute(_) => {
+				let other = direct_address(mode);
+				if other.is_none() || other == Some(address) {
+					return None;
+				}
+			}
+			_ => return None,
+		}
+	}
+	None
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn at(address: u16) -> Mode {
+		ZeroPage(Value::Number(address))
+	}
+
+	fn label(name: &str) -> Bytecode {
+		Label(name.to_string())
+	}
+
+	fn to(name: &str) -> Mode {
+		Relative(Value::symbol(name))
+	}
+
+	fn jump(name: &str) -> Bytecode {
+		JMP(Absolute(Value::symbol(name)))
+	}
+
+	fn check(rule: Rule, code: Vec<Bytecode>, expected: Vec<Bytecode>) {
+		assert_eq!(run(code, &[rule]), expected);
+	}
+
+	//The rule must leave the code as it is.
+	fn unchanged(rule: Rule, code: Vec<Bytecode>) {
+		check(rule, code.clone(), code);
+	}
+
+	#[test]
+	fn redundant_load() {
+		check(
+			super::redundant_load,
+			vec![STA(at(0x10)), LDA(at(0x10)), RTS(Implied)],
+			vec![STA(at(0x10)), RTS(Implied)],
+		);
+		check(
+			super::redundant_load,
+			vec![STX(at(0x10)), LDX(at(0x10)), RTS(Implied)],
+			vec![STX(at(0x10)), RTS(Implied)],
+		);
+
+		//Something might jump to the label with something else in A.
+		unchanged(
+			super::redundant_load,
+			vec![STA(at(0x10)), label("L1"), LDA(at(0x10)), RTS(Implied)],
+		);
+		//The branch needs the flags that the load sets.
+		unchanged(
+			super::redundant_load,
+			vec![STA(at(0x10)), LDA(at(0x10)), BEQ(to("L1")), label("L1")],
+		);
+		//A hardware register might read back differently.
+		unchanged(
+			super::redundant_load,
+			vec![
+				STA(Absolute(Value::symbol("PORT"))),
+				LDA(Absolute(Value::symbol("PORT"))),
+				RTS(Implied),
+			],
+		);
+		//So might whatever a pointer other than the stack pointer points to.
+		unchanged(
+			super::redundant_load,
+			vec![
+				STA(IndirectY(Value::symbol("POINTER"))),
+				LDA(IndirectY(Value::symbol("POINTER"))),
+				RTS(Implied),
+			],
+		);
+	}
+
+	#[test]
+	fn redundant_store() {
+		check(
+			super::redundant_store,
+			vec![LDA(at(0x10)), STA(at(0x10))],
+			vec![LDA(at(0x10))],
+		);
+
+		unchanged(
+			super::redundant_store,
+			vec![LDA(at(0x10)), label("L1"), STA(at(0x10))],
+		);
+		unchanged(super::redundant_store, vec![LDA(at(0x10)), STA(at(0x11))]);
+		check(
+			super::redundant_store,
+			vec![
+				LDA(IndirectY(Value::symbol(STACK_PTR))),
+				STA(IndirectY(Value::symbol(STACK_PTR))),
+			],
+			vec![LDA(IndirectY(Value::symbol(STACK_PTR)))],
+		);
+		//Any other pointer could be to a hardware register.
+		unchanged(
+			super::redundant_store,
+			vec![
+				LDA(IndirectY(Value::symbol("POINTER"))),
+				STA(IndirectY(Value::symbol("POINTER"))),
+			],
+		);
+		unchanged(
+			super::redundant_store,
+			vec![
+				LDA(Absolute(Value::symbol("PORT"))),
+				STA(Absolute(Value::symbol("PORT"))),
+			],
+		);
+	}
+
+	#[test]
+	fn jump_to_next() {
+		check(
+			super::jump_to_next,
+			vec![jump("L1"), label("L2"), label("L1"), RTS(Implied)],
+			vec![label("L2"), label("L1"), RTS(Implied)],
+		);
+
+		unchanged(
+			super::jump_to_next,
+			vec![jump("L1"), INX(Implied), label("L1"), RTS(Implied)],
+		);
+	}
+
+	#[test]
+	fn branch_over_jump() {
+		check(
+			super::branch_over_jump,
+			vec![
+				BEQ(to("L1")),
+				jump("L2"),
+				label("L1"),
+				INX(Implied),
+				label("L2"),
+				RTS(Implied),
+			],
+			vec![
+				BNE(to("L2")),
+				label("L1"),
+				INX(Implied),
+				label("L2"),
+				RTS(Implied),
+			],
+		);
+
+		//Too far for a branch.
+		unchanged(
+			super::branch_over_jump,
+			vec![
+				BEQ(to("L1")),
+				jump("L2"),
+				label("L1"),
+				Byte(vec![Value::Number(0); 200]),
+				label("L2"),
+				RTS(Implied),
+			],
+		);
+		//The branch doesn't go over the jump.
+		unchanged(
+			super::branch_over_jump,
+			vec![
+				BEQ(to("L3")),
+				jump("L2"),
+				label("L1"),
+				label("L2"),
+				label("L3"),
+				RTS(Implied),
+			],
+		);
+	}
+
+	#[test]
+	fn unreachable_code() {
+		check(
+			super::unreachable_code,
+			vec![
+				RTS(Implied),
+				LDA(at(0x10)),
+				Byte(vec![Value::Number(1)]),
+				label("L1"),
+				RTS(Implied),
+			],
+			vec![
+				RTS(Implied),
+				Byte(vec![Value::Number(1)]),
+				label("L1"),
+				RTS(Implied),
+			],
+		);
+
+		unchanged(
+			super::unreachable_code,
+			vec![
+				jump("L1"),
+				label("L2"),
+				INX(Implied),
+				label("L1"),
+				RTS(Implied),
+			],
+		);
+	}
+
+	#[test]
+	fn dead_store() {
+		check(
+			super::dead_store,
+			vec![STA(at(0x10)), LDA(at(0x11)), STA(at(0x10))],
+			vec![LDA(at(0x11)), STA(at(0x10))],
+		);
+
+		//Read in between.
+		unchanged(
+			super::dead_store,
+			vec![STA(at(0x10)), LDX(at(0x10)), STA(at(0x10))],
+		);
+		//Something could jump to the label and read it.
+		unchanged(
+			super::dead_store,
+			vec![STA(at(0x10)), label("L1"), STA(at(0x10))],
+		);
+		//A subroutine could read it.
+		unchanged(
+			super::dead_store,
+			vec![
+				STA(at(0x10)),
+				JSR(Absolute(Value::symbol("F"))),
+				STA(at(0x10)),
+			],
+		);
+	}
+}
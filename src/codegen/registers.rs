@@ -0,0 +1,449 @@
+use super::asm::Bytecode::{self, *};
+use super::asm::Mode::{self, *};
+use super::asm::Value;
+use super::memory::STACK_PTR;
+use crate::analysis::cfg::Cfg;
+use crate::analysis::dataflow::{self, liveness, liveness::Liveness};
+use crate::analysis::dominators::Dominators;
+use crate::ir::{
+	BinaryOp, BlockId, Comparison, Function, Inst, Operand, Temp, Terminator, UnaryOp, Width,
+};
+
+/// An index register that can hold a single-byte value instead of memory.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Register {
+	X,
+	Y,
+}
+
+/// Decide which temporaries can live in X or Y rather than in the function's frame.
+///
+/// The registers are 8 bits wide, so an `int` only qualifies if it's a loop counter that can be
+/// shown to stay from 0 to 255 (see `loop_counters`). It keeps its place in the frame too, with
+/// the high byte always zero, and the low byte is copied there whenever something other than
+/// counting or comparing with a constant needs it. Counters get first pick of the registers, and
+/// keep them for the whole function.
+///
+/// Single-byte values qualify too, which in practice means comparison results that are only used
+/// to be extended into an `int`. One of those gets a register if nothing between its definition
+/// and its last use needs that register: calls and runtime routines use all of them, and Y is
+/// needed to reach anything on the software stack.
+pub fn assign_registers(
+	function: &Function,
+	cfg: &Cfg,
+	stack_frame: bool,
+) -> Vec<Option<Register>> {
+	let mut registers = vec![None; function.temps.len()];
+	//Where each register is next free, as a position in a block. Counters use theirs throughout.
+	let mut taken = [0, 0];
+
+	//Recursive functions need Y for the stack, and X to check the stack has room (see
+	//`check_stack`), so their counters stay in memory.
+	if !stack_frame {
+		for (counter, register) in loop_counters(function, cfg)
+			.into_iter()
+			.zip([Register::X, Register::Y])
+		{
+			registers[counter.0] = Some(register);
+			taken[register as usize] = usize::MAX;
+		}
+	}
+
+	//How many times each temporary is read or written anywhere in the function.
+	let mut uses = vec![0; function.temps.len()];
+	for block in &function.blocks {
+		for inst in &block.insts {
+			for temp in inst.dest().into_iter().chain(temps_read(inst)) {
+				uses[temp.0] += 1;
+			}
+		}
+		for operand in block.terminator.operands() {
+			if let crate::ir::Operand::Temp(temp) = operand {
+				uses[temp.0] += 1;
+			}
+		}
+	}
+
+	for block in &function.blocks {
+		let mut free = taken;
+
+		for (position, inst) in block.insts.iter().enumerate() {
+			let dest = match inst {
+				Inst::Binary(dest, BinaryOp::Compare(_), _, _)
+					if function.temps[dest.0].width == Width::Byte =>
+				{
+					*dest
+				}
+				_ => continue,
+			};
+
+			//Every other use has to be an extension later in this block.
+			let readers: Vec<usize> = block.insts[position + 1..]
+				.iter()
+				.enumerate()
+				.filter(|(_, inst)| temps_read(inst).contains(&dest))
+				.map(|(offset, _)| position + 1 + offset)
+				.collect();
+			let extends_only = readers
+				.iter()
+				.all(|reader| matches!(block.insts[*reader], Inst::Unary(_, UnaryOp::Extend, _)));
+			let last = match readers.last() {
+				Some(last) if extends_only && uses[dest.0] == readers.len() + 1 => *last,
+				_ => continue,
+			};
+
+			let between = &block.insts[position + 1..last];
+			let clobbered = between.iter().any(|inst| {
+				matches!(
+					inst,
+					Inst::Call(_, _, _)
+						| Inst::Binary(_, BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem, _, _)
+				)
+			});
+			if clobbered {
+				continue;
+			}
+
+			if free[0] <= position {
+				registers[dest.0] = Some(Register::X);
+				free[0] = last;
+			} else if !stack_frame && free[1] <= position {
+				registers[dest.0] = Some(Register::Y);
+				free[1] = last;
+			}
+		}
+	}
+
+	registers
+}
+
+/// How a loop counter is changed.
+pub enum Step {
+	/// Set to a constant from 0 to 255.
+	Start(u8),
+	/// Counted up by one.
+	Increment,
+}
+
+/// Whether an instruction sets a loop counter (see `loop_counters`), and how.
+pub fn counter_step(inst: &Inst) -> Option<(Temp, Step)> {
+	match inst {
+		Inst::Copy(dest, Operand::Const(value @ 0..=255)) => {
+			Some((*dest, Step::Start(*value as u8)))
+		}
+		Inst::Binary(dest, BinaryOp::Add, Operand::Temp(a), Operand::Const(1))
+		| Inst::Binary(dest, BinaryOp::Add, Operand::Const(1), Operand::Temp(a))
+			if a == dest =>
+		{
+			Some((*dest, Step::Increment))
+		}
+		_ => None,
+	}
+}
+
+/// If a branch on a counter only goes one way when the counter is at most 254, the block that
+/// way. Counting up once from there can't go past 255.
+fn bounded_side(terminator: &Terminator, counter: Temp) -> Option<BlockId> {
+	let (comparison, limit, yes, no) = match terminator {
+		Terminator::Branch(comparison, Operand::Temp(temp), Operand::Const(limit), yes, no)
+			if *temp == counter && yes != no =>
+		{
+			(*comparison, *limit, *yes, *no)
+		}
+		_ => return None,
+	};
+	match comparison {
+		Comparison::Less if limit <= 255 => Some(yes),
+		Comparison::LessOrEqual if limit <= 254 => Some(yes),
+		Comparison::GreaterOrEqual if limit <= 255 => Some(no),
+		Comparison::Greater if limit <= 254 => Some(no),
+		_ => None,
+	}
+}
+
+//Blocks reachable from `start` without going through `avoid`, including `start`.
+fn reachable_avoiding(cfg: &Cfg, start: BlockId, avoid: BlockId) -> Vec<bool> {
+	let mut seen = vec![false; cfg.len()];
+	let mut stack = vec![start];
+	while let Some(block) = stack.pop() {
+		if block == avoid || seen[block.0] {
+			continue;
+		}
+		seen[block.0] = true;
+		stack.extend(cfg.successors[block.0].iter().copied());
+	}
+	seen
+}
+
+/// Find `int` variables that always hold 0 to 255, so they fit in a register: loop counters like
+/// `i` in `let i = 0; while i < 10 { ...; i += 1; }`, with no call or runtime routine while
+/// they're in use. A counter has to
+/// - be set only to constants from 0 to 255, and counted up by one in a single place,
+/// - be counted up only after a branch has found it to be less than 255 (the *guard*), which is
+///   the only way into the code that counts it up,
+/// - not be set or counted up again before that branch is taken once more.
+///
+/// At most two are returned, one for each register.
+fn loop_counters(function: &Function, cfg: &Cfg) -> Vec<Temp> {
+	let dominators = Dominators::build(function, cfg);
+	let live = dataflow::solve(&Liveness, function, cfg);
+	let mut counters = vec![];
+
+	'temps: for (index, info) in function.temps.iter().enumerate() {
+		let temp = Temp(index);
+		if info.width != Width::Word || function.params.contains(&temp) {
+			continue;
+		}
+
+		//Every place it's set, and where it's counted up.
+		let mut sets = vec![];
+		let mut increments = vec![];
+		for (block, insts) in function.blocks.iter().enumerate() {
+			for inst in &insts.insts {
+				if inst.dest() != Some(temp) {
+					continue;
+				}
+				match counter_step(inst) {
+					Some((_, Step::Increment)) => increments.push(BlockId(block)),
+					Some((_, Step::Start(_))) => {}
+					None => continue 'temps,
+				}
+				sets.push(BlockId(block));
+			}
+		}
+		let counted = match increments[..] {
+			[block] => block,
+			_ => continue,
+		};
+
+		let guarded = function.blocks.iter().enumerate().any(|(guard, block)| {
+			let guard = BlockId(guard);
+			let inside = match bounded_side(&block.terminator, temp) {
+				Some(inside) => inside,
+				None => return false,
+			};
+			if cfg.predecessors[inside.0] != [guard] || !dominators.dominates(inside, counted) {
+				return false;
+			}
+
+			//Between the guard and coming back to it, it's counted up once and set nowhere else.
+			let region = reachable_avoiding(cfg, inside, guard);
+			let once = sets.iter().filter(|block| region[block.0]).count() == 1;
+			let again = cfg.successors[counted.0]
+				.iter()
+				.any(|next| reachable_avoiding(cfg, *next, guard)[counted.0]);
+			once && !again
+		});
+		if !guarded {
+			continue;
+		}
+
+		//Nothing that needs the registers can happen while it's in use.
+		for (block, insts) in function.blocks.iter().enumerate() {
+			let mut after = live.exit[block].clone();
+			liveness::read(insts.terminator.operands(), &mut after);
+			for inst in insts.insts.iter().rev() {
+				let clobbers = matches!(
+					inst,
+					Inst::Call(_, _, _)
+						| Inst::Binary(_, BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem, _, _)
+				);
+				if clobbers && after.contains(index) {
+					continue 'temps;
+				}
+				liveness::step(inst, &mut after);
+			}
+		}
+
+		counters.push(temp);
+	}
+
+	counters.truncate(2);
+	counters
+}
+
+fn temps_read(inst: &Inst) -> Vec<Temp> {
+	inst.operands()
+		.into_iter()
+		.filter_map(|operand| match operand {
+			crate::ir::Operand::Temp(temp) => Some(temp),
+			_ => None,
+		})
+		.collect()
+}
+
+/// Something a register is known to hold a copy of.
+#[derive(Clone, PartialEq)]
+enum Source {
+	Immediate(Value),
+	/// A byte of memory the compiler allocated.
+	Memory(u16),
+	/// A byte in the current stack frame, at this offset from the frame pointer.
+	Stack(u16),
+	/// Some value that was worked out, which is only known to be equal to itself.
+	Computed(usize),
+}
+
+/// Keeps track of what A, X and Y hold as code is emitted, so values that are already in a
+/// register don't need loading again.
+pub struct Tracker {
+	a: Vec<Source>,
+	x: Vec<Source>,
+	y: Vec<Source>,
+	computed: usize,
+}
+
+impl Tracker {
+	pub fn new() -> Tracker {
+		Tracker {
+			a: vec![],
+			x: vec![],
+			y: vec![],
+			computed: 0,
+		}
+	}
+
+	/// What to emit instead of a load, if anything, given what the registers hold.
+	/// `None` means the register already has the value. Loads that are skipped don't set the
+	/// flags, so this mustn't be used where something depends on them.
+	pub fn load(&self, item: Bytecode) -> Option<Bytecode> {
+		let source = match &item {
+			LDA(mode) | LDX(mode) | LDY(mode) => match self.source(mode) {
+				Some(source) => source,
+				None => return Some(item),
+			},
+			_ => return Some(item),
+		};
+
+		let replacement = match item {
+			LDA(_) if self.a.contains(&source) => return None,
+			LDX(_) if self.x.contains(&source) => return None,
+			LDY(_) if self.y.contains(&source) => return None,
+			LDA(_) if self.x.contains(&source) => TXA(Implied),
+			LDA(_) if self.y.contains(&source) => TYA(Implied),
+			LDX(_) if self.a.contains(&source) => TAX(Implied),
+			LDY(_) if self.a.contains(&source) => TAY(Implied),
+			item => item,
+		};
+		Some(replacement)
+	}
+
+	/// Whether a store would write what's already in memory.
+	pub fn redundant_store(&self, item: &Bytecode) -> bool {
+		let (register, mode) = match item {
+			STA(mode) => (&self.a, mode),
+			STX(mode) => (&self.x, mode),
+			STY(mode) => (&self.y, mode),
+			_ => return false,
+		};
+		match self.source(mode) {
+			Some(source @ (Source::Memory(_) | Source::Stack(_))) => register.contains(&source),
+			_ => false,
+		}
+	}
+
+	/// Update what the registers hold after an item has been emitted.
+	pub fn update(&mut self, item: &Bytecode) {
+		match item {
+			LDA(mode) => self.a = self.loaded(mode),
+			LDX(mode) => self.x = self.loaded(mode),
+			LDY(mode) => self.y = self.loaded(mode),
+
+			STA(mode) | STX(mode) | STY(mode) => {
+				self.overwrite(mode);
+				if let Some(source) = self.source(mode) {
+					match item {
+						STA(_) => self.a.push(source),
+						STX(_) => self.x.push(source),
+						_ => self.y.push(source),
+					}
+				}
+			}
+
+			TAX(_) => self.x = self.a.clone(),
+			TAY(_) => self.y = self.a.clone(),
+			TXA(_) => self.a = self.x.clone(),
+			TYA(_) => self.a = self.y.clone(),
+			TSX(_) => self.x = self.computed(),
+
+			INX(_) | DEX(_) => self.x = self.computed(),
+			INY(_) | DEY(_) => {
+				//Stepping through the bytes of a value on the stack is common enough to follow.
+				let step: i32 = if let INY(_) = item { 1 } else { -1 };
+				let known = self.y.iter().find_map(|source| match source {
+					Source::Immediate(Value::Number(value)) => Some(*value),
+					_ => None,
+				});
+				self.y = match known {
+					Some(value) => vec![Source::Immediate(Value::Number(
+						(value as i32 + step) as u16 & 0xFF,
+					))],
+					None => self.computed(),
+				};
+			}
+
+			ADC(_) | SBC(_) | AND(_) | ORA(_) | EOR(_) | PLA(_) => self.a = self.computed(),
+
+			ASL(mode) | LSR(mode) | ROL(mode) | ROR(mode) => match mode {
+				Accumulator => self.a = self.computed(),
+				mode => self.overwrite(mode),
+			},
+			INC(mode) | DEC(mode) => self.overwrite(mode),
+
+			//Nothing is known where control can arrive from somewhere else, or after a call.
+			Label(_) | JSR(_) | JMP(_) | RTS(_) | RTI(_) | BRK(_) => {
+				self.a.clear();
+				self.x.clear();
+				self.y.clear();
+			}
+
+			//These don't change any registers.
+			BCC(_) | BCS(_) | BEQ(_) | BMI(_) | BNE(_) | BPL(_) | BVC(_) | BVS(_) | BIT(_)
+			| CMP(_) | CPX(_) | CPY(_) | CLC(_) | CLD(_) | CLI(_) | CLV(_) | SEC(_) | SED(_)
+			| SEI(_) | NOP(_) | PHA(_) | PHP(_) | PLP(_) | TXS(_) => {}
+			Define(_, _) | Org(_) | Byte(_) | Word(_) | Res(_) | Align(_) => {}
+		}
+	}
+
+	fn computed(&mut self) -> Vec<Source> {
+		self.computed += 1;
+		vec![Source::Computed(self.computed)]
+	}
+
+	fn loaded(&mut self, mode: &Mode) -> Vec<Source> {
+		match self.source(mode) {
+			Some(source) => vec![source],
+			None => self.computed(),
+		}
+	}
+
+	//What an operand refers to, if it's something that can be tracked.
+	fn source(&self, mode: &Mode) -> Option<Source> {
+		match mode {
+			Immediate(value) => Some(Source::Immediate(value.clone())),
+			ZeroPage(Value::Number(address)) | Absolute(Value::Number(address)) => {
+				Some(Source::Memory(*address))
+			}
+			IndirectY(Value::Symbol(name, 0)) if name == STACK_PTR => {
+				self.y.iter().find_map(|source| match source {
+					Source::Immediate(Value::Number(offset)) => Some(Source::Stack(*offset)),
+					_ => None,
+				})
+			}
+			_ => None,
+		}
+	}
+
+	//Memory is about to change, so registers no longer hold copies of it.
+	fn overwrite(&mut self, mode: &Mode) {
+		let target = self.source(mode);
+		for register in [&mut self.a, &mut self.x, &mut self.y] {
+			register.retain(|source| match (&target, source) {
+				(Some(target), source) => target != source,
+				//Anything else, like a named address or the frame pointer, could be anywhere.
+				(None, Source::Memory(_) | Source::Stack(_)) => false,
+				(None, _) => true,
+			});
+		}
+	}
+}
@@ -0,0 +1,221 @@
+use super::asm::Bytecode;
+use super::asm::Bytecode::*;
+use super::asm::Mode;
+use super::asm::Mode::*;
+use super::asm::Value;
+use super::asm::Value::*;
+use super::memory::{Loc, Workspace};
+use crate::target;
+
+/// Subroutines that generated code may call. Each one is only emitted if it is used.
+///
+/// Arithmetic follows the same rules as most modern languages:
+/// * All operations wrap around on overflow (e.g. `-32768 * -1 == -32768`).
+/// * Division truncates toward zero, so `-7 / 2 == -3`.
+/// * The remainder has the same sign as the dividend, so `-7 % 2 == -1`
+///   and `a == (a / b) * b + a % b` always holds.
+/// * Dividing by zero doesn't crash: `a / 0 == -1` and `a % 0 == a`.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Routine {
+	/// result = lhs * rhs
+	Multiply,
+	/// lhs = lhs / rhs, rem = lhs % rhs (signed)
+	Divide,
+	/// Same as `Divide`, but for unsigned values. The divisor must be at most $8000.
+	UnsignedDivide,
+	/// Print lhs in decimal, on a line of its own, with the target's routines.
+	Print,
+}
+
+use Routine::*;
+
+fn label(name: &str) -> Bytecode {
+	Label(name.to_string())
+}
+
+fn branch(name: &str) -> Mode {
+	Relative(Value::symbol(name))
+}
+
+//Negate a 16-bit value in place.
+fn negate(code: &mut Vec<Bytecode>, loc: Loc) {
+	code.push(SEC(Implied));
+	for n in 0..2 {
+		code.push(LDA(Immediate(Number(0))));
+		code.push(SBC(loc.byte(n)));
+		code.push(STA(loc.byte(n)));
+	}
+}
+
+impl Routine {
+	pub fn label(&self) -> &'static str {
+		match self {
+			Multiply => "MATH_mul16",
+			Divide => "MATH_div16",
+			UnsignedDivide => "MATH_udiv16",
+			Print => "PRINT_int",
+		}
+	}
+
+	/// Other routines that this one calls.
+	pub fn dependencies(&self) -> Vec<Routine> {
+		match self {
+			Divide | Print => vec![UnsignedDivide],
+			_ => vec![],
+		}
+	}
+
+	pub fn code(&self, workspace: &Workspace) -> Vec<Bytecode> {
+		let lhs = |n| workspace.lhs.byte(n);
+		let rhs = |n| workspace.rhs.byte(n);
+		let result = |n| workspace.result.byte(n);
+		let rem = |n| workspace.rem.byte(n);
+		let mut code = vec![label(self.label())];
+
+		match self {
+			//Shift-and-add. Only the low 16 bits of the product are kept,
+			//which are the same whether the inputs are signed or not.
+			Multiply => {
+				code.extend([
+					LDA(Immediate(Number(0))),
+					STA(result(0)),
+					STA(result(1)),
+					LDX(Immediate(Number(16))),
+					label("MATH_mul16_loop"),
+					LSR(rhs(1)),
+					ROR(rhs(0)),
+					BCC(branch("MATH_mul16_skip")),
+					CLC(Implied),
+					LDA(result(0)),
+					ADC(lhs(0)),
+					STA(result(0)),
+					LDA(result(1)),
+					ADC(lhs(1)),
+					STA(result(1)),
+					label("MATH_mul16_skip"),
+					ASL(lhs(0)),
+					ROL(lhs(1)),
+					DEX(Implied),
+					BNE(branch("MATH_mul16_loop")),
+					RTS(Implied),
+				]);
+			}
+
+			//Divide the magnitudes, then fix up the signs.
+			Divide => {
+				code.extend([
+					LDA(rhs(0)),
+					ORA(rhs(1)),
+					BNE(branch("MATH_div16_nonzero")),
+					//Division by zero
+					LDA(lhs(0)),
+					STA(rem(0)),
+					LDA(lhs(1)),
+					STA(rem(1)),
+					LDA(Immediate(Number(0xFF))),
+					STA(lhs(0)),
+					STA(lhs(1)),
+					RTS(Implied),
+					label("MATH_div16_nonzero"),
+					//The quotient is negative if exactly one input is,
+					//the remainder is negative if the dividend is.
+					LDA(lhs(1)),
+					EOR(rhs(1)),
+					STA(workspace.sign.byte(0)),
+					LDA(lhs(1)),
+					PHA(Implied),
+					BPL(branch("MATH_div16_lhs_positive")),
+				]);
+				negate(&mut code, workspace.lhs);
+				code.extend([
+					label("MATH_div16_lhs_positive"),
+					LDA(rhs(1)),
+					BPL(branch("MATH_div16_rhs_positive")),
+				]);
+				negate(&mut code, workspace.rhs);
+				code.extend([
+					label("MATH_div16_rhs_positive"),
+					JSR(Absolute(Value::symbol(UnsignedDivide.label()))),
+					LDA(workspace.sign.byte(0)),
+					BPL(branch("MATH_div16_quotient_positive")),
+				]);
+				negate(&mut code, workspace.lhs);
+				code.extend([
+					label("MATH_div16_quotient_positive"),
+					PLA(Implied),
+					BPL(branch("MATH_div16_done")),
+				]);
+				negate(&mut code, workspace.rem);
+				code.extend([label("MATH_div16_done"), RTS(Implied)]);
+			}
+
+			//Restoring shift-and-subtract division.
+			//The quotient is shifted into `lhs` as the dividend is shifted out.
+			UnsignedDivide => {
+				code.extend([
+					LDA(Immediate(Number(0))),
+					STA(rem(0)),
+					STA(rem(1)),
+					LDX(Immediate(Number(16))),
+					label("MATH_udiv16_loop"),
+					ASL(lhs(0)),
+					ROL(lhs(1)),
+					ROL(rem(0)),
+					ROL(rem(1)),
+					SEC(Implied),
+					LDA(rem(0)),
+					SBC(rhs(0)),
+					TAY(Implied),
+					LDA(rem(1)),
+					SBC(rhs(1)),
+					BCC(branch("MATH_udiv16_skip")),
+					STA(rem(1)),
+					STY(rem(0)),
+					INC(lhs(0)),
+					label("MATH_udiv16_skip"),
+					DEX(Implied),
+					BNE(branch("MATH_udiv16_loop")),
+					RTS(Implied),
+				]);
+			}
+
+			//Digits come out lowest first, so they're pushed on the stack (on top of a zero that
+			//marks the end) and then printed as they're popped off.
+			Print => {
+				let print_char = || JSR(Absolute(Value::symbol(target::PRINT_CHAR)));
+				code.extend([
+					LDA(lhs(1)),
+					BPL(branch("PRINT_int_positive")),
+					LDA(Immediate(Number('-' as u16))),
+					print_char(),
+				]);
+				negate(&mut code, workspace.lhs);
+				code.extend([
+					label("PRINT_int_positive"),
+					LDA(Immediate(Number(0))),
+					PHA(Implied),
+					STA(rhs(1)),
+					label("PRINT_int_digit"),
+					LDA(Immediate(Number(10))),
+					STA(rhs(0)),
+					JSR(Absolute(Value::symbol(UnsignedDivide.label()))),
+					LDA(rem(0)),
+					ORA(Immediate(Number('0' as u16))),
+					PHA(Implied),
+					LDA(lhs(0)),
+					ORA(lhs(1)),
+					BNE(branch("PRINT_int_digit")),
+					label("PRINT_int_char"),
+					PLA(Implied),
+					BEQ(branch("PRINT_int_done")),
+					print_char(),
+					JMP(Absolute(Value::symbol("PRINT_int_char"))),
+					label("PRINT_int_done"),
+					JMP(Absolute(Value::symbol(target::PRINT_NEWLINE))),
+				]);
+			}
+		}
+
+		code
+	}
+}
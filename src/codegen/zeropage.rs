@@ -0,0 +1,44 @@
+/// Keeps track of which zero page addresses the compiler is allowed to use.
+/// Zero page is faster and smaller to access than the rest of memory, and it's the only place
+/// pointers can go, but there are only 256 bytes of it and the platform usually needs some.
+pub struct ZeroPage {
+	free: [bool; 256],
+}
+
+impl ZeroPage {
+	/// Everything is free except for the given (inclusive) address ranges.
+	pub fn new(reserved: &[(u8, u8)]) -> ZeroPage {
+		let mut free = [true; 256];
+		for (first, last) in reserved {
+			for address in *first..=*last {
+				free[address as usize] = false;
+			}
+		}
+		ZeroPage { free }
+	}
+
+	/// Take `size` consecutive free bytes, returning the first address.
+	pub fn alloc(&mut self, size: u16) -> Option<u16> {
+		let size = size as usize;
+		let mut start = 0;
+		while start + size <= self.free.len() {
+			match self.free[start..start + size].iter().position(|free| !free) {
+				Some(used) => start += used + 1,
+				None => {
+					self.free[start..start + size].fill(false);
+					return Some(start as u16);
+				}
+			}
+		}
+		None
+	}
+
+	/// Take every remaining pair of consecutive free bytes, returning the first address of each.
+	pub fn alloc_pairs(&mut self) -> Vec<u16> {
+		let mut pairs = vec![];
+		while let Some(address) = self.alloc(2) {
+			pairs.push(address);
+		}
+		pairs
+	}
+}
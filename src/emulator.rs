@@ -0,0 +1,222 @@
+//An emulated 6502 computer, so compiled programs can be run (and checked) without real hardware.
+
+mod cpu;
+mod lcd;
+mod memory;
+
+use crate::codegen::asm::Bytecode::{self, *};
+use crate::codegen::asm::Mode::*;
+use crate::codegen::asm::Value::{self, *};
+use crate::lexer::Span;
+use crate::target::{self, Print, Target};
+use cpu::{Bus, Cpu};
+pub use memory::{Device, MemoryMap, Region};
+
+/// Where programs are loaded, at the start of a 32K ROM.
+pub const ROM_START: u16 = 0x8000;
+/// Every byte written here is printed.
+pub const OUTPUT: u16 = 0x6000;
+//Where the firmware keeps its variables, just past the top of the software stack.
+const FIRMWARE_RAM: u16 = 0x4000;
+//Where a failed assertion leaves its details: the source span, then the values for `assert_eq`,
+//then which kind of assertion it was (zero if none failed).
+const ASSERT_SPAN: u16 = FIRMWARE_RAM;
+const ASSERT_LEFT: u16 = ASSERT_SPAN + 4;
+const ASSERT_RIGHT: u16 = ASSERT_SPAN + 6;
+const ASSERT_KIND: u16 = ASSERT_SPAN + 8;
+
+/// How long a program can run before it's assumed to be stuck (50 seconds at 1MHz).
+pub const MAX_CYCLES: u64 = 50_000_000;
+
+//The layout of the emulated computer: 24K of RAM, an output port, and 32K of ROM at the top.
+fn regions() -> Vec<Region> {
+	vec![
+		Region {
+			start: 0x0000,
+			end: 0x5FFF,
+			device: Device::Ram,
+		},
+		Region {
+			start: OUTPUT,
+			end: OUTPUT,
+			device: Device::Output,
+		},
+		Region {
+			start: ROM_START,
+			end: 0xFFFF,
+			device: Device::Rom,
+		},
+	]
+}
+
+/// What the emulator needs to stand in for the computer a target is for.
+pub struct Hardware {
+	/// What each part of the address space is connected to.
+	pub regions: Vec<Region>,
+	/// The computer's own ROM, if programs rely on it, and the address it's assembled at.
+	pub firmware: Option<(u16, Vec<Bytecode>)>,
+}
+
+/// A CPU connected to some memory.
+pub struct Machine {
+	cpu: Cpu,
+	pub memory: MemoryMap,
+}
+
+impl Machine {
+	pub fn new(regions: Vec<Region>) -> Machine {
+		Machine {
+			cpu: Cpu::new(),
+			memory: MemoryMap::new(regions),
+		}
+	}
+
+	/// Load a program image, which should include the vectors.
+	pub fn load(&mut self, image: &[u8], origin: u16) -> Result<(), String> {
+		self.memory.load(origin, image)
+	}
+
+	/// Reset the CPU and run until the program halts by jumping to itself.
+	/// Returns an error if it crashes (runs into `brk` or an illegal opcode) or doesn't halt in time.
+	pub fn run(&mut self, max_cycles: u64) -> Result<(), String> {
+		self.cpu.reset(&mut self.memory);
+
+		loop {
+			let address = self.cpu.pc;
+			//Generated code never uses `brk`, so it most likely means running off into empty memory.
+			if self.memory.read(address) == 0x00 {
+				return Err(format!(
+					"Program crashed: reached `brk` at ${:04X}",
+					address
+				));
+			}
+
+			self.cpu.step(&mut self.memory)?;
+			if self.cpu.pc == address {
+				return Ok(());
+			}
+
+			if self.cpu.cycles > max_cycles {
+				return Err(format!("Program did not halt within {} cycles", max_cycles));
+			}
+		}
+	}
+
+	/// Clock cycles used so far.
+	pub fn cycles(&self) -> u64 {
+		self.cpu.cycles
+	}
+
+	/// The assertion that stopped the program, if one did, or whether it ran out of stack.
+	pub fn failure(&self) -> Option<Failure> {
+		let span = Span {
+			lo: self.memory.word(ASSERT_SPAN) as usize,
+			hi: self.memory.word(ASSERT_SPAN + 2) as usize,
+		};
+		let values = (
+			self.memory.word(ASSERT_LEFT) as i16,
+			self.memory.word(ASSERT_RIGHT) as i16,
+		);
+		match self.memory.word(ASSERT_KIND) {
+			0 => None,
+			1 => Some(Failure::Assertion { span, values: None }),
+			target::OUT_OF_STACK => Some(Failure::OutOfStack),
+			_ => Some(Failure::Assertion {
+				span,
+				values: Some(values),
+			}),
+		}
+	}
+}
+
+/// Why a program stopped early.
+pub enum Failure {
+	/// An assertion didn't hold.
+	Assertion {
+		/// Where the assertion is in the source.
+		span: Span,
+		/// What `assert_eq` was given.
+		values: Option<(i16, i16)>,
+	},
+	/// A recursive function was called with no room left on the hardware or software stack.
+	OutOfStack,
+}
+
+/// The emulated computer as a target: programs get all of ROM, with the vectors at the top, and
+/// print to the output port. A failed assertion leaves its details where `Machine::failure()`
+/// can find them, then halts.
+pub fn target() -> Target {
+	let output = || Value::symbol("OUTPUT");
+	Target {
+		name: "emulator".to_string(),
+		rom: (ROM_START, 0xFFFF),
+		//The software stack grows down from the firmware's variables.
+		ram: (0x0200, FIRMWARE_RAM - 1),
+		zp_reserved: vec![],
+		vectors: Some(0xFFFA),
+		header: vec![],
+		load_address: false,
+		startup: target::reset(),
+		exit: target::halt("halt"),
+		print: Some(Print {
+			char: vec![STA(Absolute(output())), RTS(Implied)],
+			newline: vec![
+				LDA(Immediate(Number('\n' as u16))),
+				STA(Absolute(output())),
+				RTS(Implied),
+			],
+		}),
+		assert_failed: [STA(Absolute(Number(ASSERT_KIND)))]
+			.into_iter()
+			.chain(target::halt("ASSERT_halt"))
+			.collect(),
+		assert_details: Some(ASSERT_SPAN),
+		runtime: vec![Define("OUTPUT".to_string(), Number(OUTPUT))],
+		hardware: Some(Hardware {
+			regions: regions(),
+			firmware: None,
+		}),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	//A machine with a program at the start of ROM, and the reset vector pointing to it.
+	fn boot(program: &[u8]) -> Machine {
+		let mut image = vec![0; 0x8000];
+		image[..program.len()].copy_from_slice(program);
+		image[0x7FFC..0x7FFE].copy_from_slice(&ROM_START.to_le_bytes());
+		let mut machine = Machine::new(regions());
+		machine.load(&image, ROM_START).unwrap();
+		machine
+	}
+
+	#[test]
+	fn halts() {
+		//lda #'A', sta OUTPUT, jmp *
+		let mut machine = boot(&[0xA9, b'A', 0x8D, 0x00, 0x60, 0x4C, 0x05, 0x80]);
+		assert_eq!(machine.run(MAX_CYCLES), Ok(()));
+		assert_eq!(machine.memory.output, b"A");
+		//The reset, then 2 + 4 + 3.
+		assert_eq!(machine.cycles(), 16);
+	}
+
+	#[test]
+	fn crashes() {
+		//nop, then running into empty ROM.
+		let mut machine = boot(&[0xEA]);
+		assert_eq!(
+			machine.run(MAX_CYCLES),
+			Err("Program crashed: reached `brk` at $8001".to_string())
+		);
+
+		//jmp to the start, forever.
+		let mut machine = boot(&[0xEA, 0x4C, 0x00, 0x80]);
+		assert_eq!(
+			machine.run(100),
+			Err("Program did not halt within 100 cycles".to_string())
+		);
+	}
+}
@@ -0,0 +1,764 @@
+use crate::codegen::asm;
+
+/// Anything the CPU can read from and write to.
+pub trait Bus {
+	fn read(&mut self, address: u16) -> u8;
+	fn write(&mut self, address: u16, value: u8);
+}
+
+//Status register flags
+pub const CARRY: u8 = 0x01;
+pub const ZERO: u8 = 0x02;
+pub const INTERRUPT: u8 = 0x04;
+pub const DECIMAL: u8 = 0x08;
+pub const BREAK: u8 = 0x10;
+pub const UNUSED: u8 = 0x20;
+pub const OVERFLOW: u8 = 0x40;
+pub const NEGATIVE: u8 = 0x80;
+
+//Addressing mode columns, matching the opcode table in `codegen::asm`.
+const IMPLIED: usize = 0;
+const ACCUMULATOR: usize = 1;
+const IMMEDIATE: usize = 2;
+const ZERO_PAGE: usize = 3;
+const ZERO_PAGE_X: usize = 4;
+const ZERO_PAGE_Y: usize = 5;
+const ABSOLUTE: usize = 6;
+const ABSOLUTE_X: usize = 7;
+const ABSOLUTE_Y: usize = 8;
+const INDIRECT: usize = 9;
+const INDIRECT_X: usize = 10;
+const INDIRECT_Y: usize = 11;
+const RELATIVE: usize = 12;
+
+/// An NMOS 6502, accurate to the instruction (not to the individual bus cycle).
+pub struct Cpu {
+	pub a: u8,
+	pub x: u8,
+	pub y: u8,
+	pub sp: u8,
+	pub pc: u16,
+	pub status: u8,
+	pub cycles: u64,
+	//The instruction and addressing mode of every opcode, looked up once.
+	decoded: Vec<Option<(&'static str, usize)>>,
+}
+
+//Where an instruction's operand lives.
+enum Operand {
+	None,
+	Accumulator,
+	Immediate(u8),
+	Address(u16),
+}
+
+impl Cpu {
+	pub fn new() -> Cpu {
+		Cpu {
+			a: 0,
+			x: 0,
+			y: 0,
+			sp: 0xFD,
+			pc: 0,
+			status: UNUSED | INTERRUPT,
+			cycles: 0,
+			decoded: (0..=255).map(asm::decode).collect(),
+		}
+	}
+
+	/// Load the program counter from the reset vector, like the real chip does on power up.
+	pub fn reset(&mut self, bus: &mut dyn Bus) {
+		self.pc = read_word(bus, 0xFFFC);
+		self.sp = 0xFD;
+		self.status = UNUSED | INTERRUPT;
+		self.cycles += 7;
+	}
+
+	//Only `brk` interrupts for now, since there's no hardware to raise IRQ or NMI.
+	fn interrupt(&mut self, bus: &mut dyn Bus, vector: u16) {
+		self.push(bus, (self.pc >> 8) as u8);
+		self.push(bus, self.pc as u8);
+		self.push(bus, self.status | BREAK | UNUSED);
+		self.set_flag(INTERRUPT, true);
+		self.pc = read_word(bus, vector);
+		self.cycles += 7;
+	}
+
+	pub fn flag(&self, flag: u8) -> bool {
+		self.status & flag != 0
+	}
+
+	fn set_flag(&mut self, flag: u8, value: bool) {
+		if value {
+			self.status |= flag;
+		} else {
+			self.status &= !flag;
+		}
+	}
+
+	fn set_nz(&mut self, value: u8) {
+		self.set_flag(ZERO, value == 0);
+		self.set_flag(NEGATIVE, value & 0x80 != 0);
+	}
+
+	fn push(&mut self, bus: &mut dyn Bus, value: u8) {
+		bus.write(0x0100 | self.sp as u16, value);
+		self.sp = self.sp.wrapping_sub(1);
+	}
+
+	fn pull(&mut self, bus: &mut dyn Bus) -> u8 {
+		self.sp = self.sp.wrapping_add(1);
+		bus.read(0x0100 | self.sp as u16)
+	}
+
+	fn fetch(&mut self, bus: &mut dyn Bus) -> u8 {
+		let value = bus.read(self.pc);
+		self.pc = self.pc.wrapping_add(1);
+		value
+	}
+
+	fn fetch_word(&mut self, bus: &mut dyn Bus) -> u16 {
+		let lo = self.fetch(bus) as u16;
+		let hi = self.fetch(bus) as u16;
+		hi << 8 | lo
+	}
+
+	//Decode the operand for the given addressing mode.
+	//Also returns whether a page boundary was crossed while indexing.
+	fn operand(&mut self, bus: &mut dyn Bus, mode: usize) -> (Operand, bool) {
+		match mode {
+			IMPLIED => (Operand::None, false),
+			ACCUMULATOR => (Operand::Accumulator, false),
+			IMMEDIATE => (Operand::Immediate(self.fetch(bus)), false),
+			ZERO_PAGE => (Operand::Address(self.fetch(bus) as u16), false),
+			ZERO_PAGE_X => (
+				Operand::Address(self.fetch(bus).wrapping_add(self.x) as u16),
+				false,
+			),
+			ZERO_PAGE_Y => (
+				Operand::Address(self.fetch(bus).wrapping_add(self.y) as u16),
+				false,
+			),
+			ABSOLUTE => (Operand::Address(self.fetch_word(bus)), false),
+			ABSOLUTE_X | ABSOLUTE_Y => {
+				let base = self.fetch_word(bus);
+				let index = if mode == ABSOLUTE_X { self.x } else { self.y };
+				let address = base.wrapping_add(index as u16);
+				(Operand::Address(address), base & 0xFF00 != address & 0xFF00)
+			}
+			INDIRECT => {
+				//The original 6502 doesn't carry into the high byte when fetching the pointer.
+				let pointer = self.fetch_word(bus);
+				let lo = bus.read(pointer) as u16;
+				let hi = bus.read((pointer & 0xFF00) | (pointer.wrapping_add(1) & 0x00FF)) as u16;
+				(Operand::Address(hi << 8 | lo), false)
+			}
+			INDIRECT_X => {
+				let pointer = self.fetch(bus).wrapping_add(self.x);
+				(Operand::Address(read_zp_word(bus, pointer)), false)
+			}
+			INDIRECT_Y => {
+				let pointer = self.fetch(bus);
+				let base = read_zp_word(bus, pointer);
+				let address = base.wrapping_add(self.y as u16);
+				(Operand::Address(address), base & 0xFF00 != address & 0xFF00)
+			}
+			RELATIVE => {
+				let offset = self.fetch(bus) as i8;
+				(Operand::Address(self.pc.wrapping_add(offset as u16)), false)
+			}
+			_ => panic!("Invalid addressing mode"),
+		}
+	}
+
+	fn load(&mut self, bus: &mut dyn Bus, operand: &Operand) -> u8 {
+		match operand {
+			Operand::Accumulator => self.a,
+			Operand::Immediate(value) => *value,
+			Operand::Address(address) => bus.read(*address),
+			Operand::None => panic!("Instruction has no operand"),
+		}
+	}
+
+	fn store(&mut self, bus: &mut dyn Bus, operand: &Operand, value: u8) {
+		match operand {
+			Operand::Accumulator => self.a = value,
+			Operand::Address(address) => bus.write(*address, value),
+			_ => panic!("Cannot store to operand"),
+		}
+	}
+
+	fn address(operand: &Operand) -> u16 {
+		match operand {
+			Operand::Address(address) => *address,
+			_ => panic!("Instruction needs an address"),
+		}
+	}
+
+	fn add(&mut self, value: u8) {
+		let carry = self.flag(CARRY) as u16;
+		let binary = self.a as u16 + value as u16 + carry;
+		self.set_flag(
+			OVERFLOW,
+			(!(self.a ^ value) & (self.a ^ binary as u8)) & 0x80 != 0,
+		);
+
+		if self.flag(DECIMAL) {
+			let mut lo = (self.a & 0x0F) as u16 + (value & 0x0F) as u16 + carry;
+			let mut hi = (self.a >> 4) as u16 + (value >> 4) as u16;
+			if lo > 9 {
+				lo += 6;
+				hi += 1;
+			}
+			if hi > 9 {
+				hi += 6;
+			}
+			self.set_flag(CARRY, hi > 15);
+			self.a = ((hi << 4) | (lo & 0x0F)) as u8;
+			self.set_flag(ZERO, binary as u8 == 0);
+			self.set_flag(NEGATIVE, self.a & 0x80 != 0);
+		} else {
+			self.set_flag(CARRY, binary > 0xFF);
+			self.a = binary as u8;
+			self.set_nz(self.a);
+		}
+	}
+
+	fn subtract(&mut self, value: u8) {
+		if self.flag(DECIMAL) {
+			let borrow = !self.flag(CARRY) as i16;
+			let binary = self.a as i16 - value as i16 - borrow;
+			let mut lo = (self.a & 0x0F) as i16 - (value & 0x0F) as i16 - borrow;
+			let mut hi = (self.a >> 4) as i16 - (value >> 4) as i16;
+			if lo < 0 {
+				lo -= 6;
+				hi -= 1;
+			}
+			if hi < 0 {
+				hi -= 6;
+			}
+			self.set_flag(
+				OVERFLOW,
+				((self.a ^ value) & (self.a ^ binary as u8)) & 0x80 != 0,
+			);
+			self.set_flag(CARRY, binary >= 0);
+			self.set_nz(binary as u8);
+			self.a = ((hi << 4) | (lo & 0x0F)) as u8;
+		} else {
+			self.add(!value);
+		}
+	}
+
+	fn compare(&mut self, register: u8, value: u8) {
+		self.set_flag(CARRY, register >= value);
+		self.set_nz(register.wrapping_sub(value));
+	}
+
+	/// Execute a single instruction. Returns an error for opcodes the 6502 doesn't document.
+	pub fn step(&mut self, bus: &mut dyn Bus) -> Result<(), String> {
+		let address = self.pc;
+		let opcode = self.fetch(bus);
+		let (mnemonic, mode) = match self.decoded[opcode as usize] {
+			None => {
+				return Err(format!(
+					"Illegal opcode ${:02X} at ${:04X}",
+					opcode, address
+				))
+			}
+			Some(decoded) => decoded,
+		};
+
+		let (operand, page_crossed) = self.operand(bus, mode);
+		self.cycles += cycles(mnemonic, mode) as u64;
+
+		//Reads pay an extra cycle when indexing crosses a page boundary.
+		if page_crossed
+			&& !matches!(
+				mnemonic,
+				"sta" | "asl" | "lsr" | "rol" | "ror" | "inc" | "dec"
+			) {
+			self.cycles += 1;
+		}
+
+		match mnemonic {
+			//Loads and stores
+			"lda" => {
+				self.a = self.load(bus, &operand);
+				self.set_nz(self.a);
+			}
+			"ldx" => {
+				self.x = self.load(bus, &operand);
+				self.set_nz(self.x);
+			}
+			"ldy" => {
+				self.y = self.load(bus, &operand);
+				self.set_nz(self.y);
+			}
+			"sta" => self.store(bus, &operand, self.a),
+			"stx" => self.store(bus, &operand, self.x),
+			"sty" => self.store(bus, &operand, self.y),
+
+			//Register transfers
+			"tax" => {
+				self.x = self.a;
+				self.set_nz(self.x);
+			}
+			"tay" => {
+				self.y = self.a;
+				self.set_nz(self.y);
+			}
+			"txa" => {
+				self.a = self.x;
+				self.set_nz(self.a);
+			}
+			"tya" => {
+				self.a = self.y;
+				self.set_nz(self.a);
+			}
+			"tsx" => {
+				self.x = self.sp;
+				self.set_nz(self.x);
+			}
+			"txs" => self.sp = self.x,
+
+			//Stack
+			"pha" => self.push(bus, self.a),
+			"php" => self.push(bus, self.status | BREAK | UNUSED),
+			"pla" => {
+				self.a = self.pull(bus);
+				self.set_nz(self.a);
+			}
+			"plp" => self.status = (self.pull(bus) & !BREAK) | UNUSED,
+
+			//Arithmetic and logic
+			"adc" => {
+				let value = self.load(bus, &operand);
+				self.add(value);
+			}
+			"sbc" => {
+				let value = self.load(bus, &operand);
+				self.subtract(value);
+			}
+			"and" => {
+				self.a &= self.load(bus, &operand);
+				self.set_nz(self.a);
+			}
+			"ora" => {
+				self.a |= self.load(bus, &operand);
+				self.set_nz(self.a);
+			}
+			"eor" => {
+				self.a ^= self.load(bus, &operand);
+				self.set_nz(self.a);
+			}
+			"bit" => {
+				let value = self.load(bus, &operand);
+				self.set_flag(ZERO, self.a & value == 0);
+				self.set_flag(OVERFLOW, value & 0x40 != 0);
+				self.set_flag(NEGATIVE, value & 0x80 != 0);
+			}
+			"cmp" => {
+				let value = self.load(bus, &operand);
+				self.compare(self.a, value);
+			}
+			"cpx" => {
+				let value = self.load(bus, &operand);
+				self.compare(self.x, value);
+			}
+			"cpy" => {
+				let value = self.load(bus, &operand);
+				self.compare(self.y, value);
+			}
+
+			//Increments and decrements
+			"inc" | "dec" => {
+				let value = self.load(bus, &operand);
+				let value = if mnemonic == "inc" {
+					value.wrapping_add(1)
+				} else {
+					value.wrapping_sub(1)
+				};
+				self.store(bus, &operand, value);
+				self.set_nz(value);
+			}
+			"inx" => {
+				self.x = self.x.wrapping_add(1);
+				self.set_nz(self.x);
+			}
+			"iny" => {
+				self.y = self.y.wrapping_add(1);
+				self.set_nz(self.y);
+			}
+			"dex" => {
+				self.x = self.x.wrapping_sub(1);
+				self.set_nz(self.x);
+			}
+			"dey" => {
+				self.y = self.y.wrapping_sub(1);
+				self.set_nz(self.y);
+			}
+
+			//Shifts
+			"asl" | "lsr" | "rol" | "ror" => {
+				let value = self.load(bus, &operand);
+				let carry = self.flag(CARRY) as u8;
+				let (result, carry_out) = match mnemonic {
+					"asl" => (value << 1, value & 0x80 != 0),
+					"lsr" => (value >> 1, value & 0x01 != 0),
+					"rol" => ((value << 1) | carry, value & 0x80 != 0),
+					_ => ((value >> 1) | (carry << 7), value & 0x01 != 0),
+				};
+				self.store(bus, &operand, result);
+				self.set_flag(CARRY, carry_out);
+				self.set_nz(result);
+			}
+
+			//Jumps and calls
+			"jmp" => self.pc = Cpu::address(&operand),
+			"jsr" => {
+				let return_address = self.pc.wrapping_sub(1);
+				self.push(bus, (return_address >> 8) as u8);
+				self.push(bus, return_address as u8);
+				self.pc = Cpu::address(&operand);
+			}
+			"rts" => {
+				let lo = self.pull(bus) as u16;
+				let hi = self.pull(bus) as u16;
+				self.pc = (hi << 8 | lo).wrapping_add(1);
+			}
+			"rti" => {
+				self.status = (self.pull(bus) & !BREAK) | UNUSED;
+				let lo = self.pull(bus) as u16;
+				let hi = self.pull(bus) as u16;
+				self.pc = hi << 8 | lo;
+			}
+			"brk" => {
+				//BRK skips over a padding byte.
+				self.pc = self.pc.wrapping_add(1);
+				self.cycles -= 7; //Counted by `interrupt()`
+				self.interrupt(bus, 0xFFFE);
+			}
+
+			//Branches
+			"bcc" | "bcs" | "beq" | "bne" | "bmi" | "bpl" | "bvc" | "bvs" => {
+				let taken = match mnemonic {
+					"bcc" => !self.flag(CARRY),
+					"bcs" => self.flag(CARRY),
+					"beq" => self.flag(ZERO),
+					"bne" => !self.flag(ZERO),
+					"bmi" => self.flag(NEGATIVE),
+					"bpl" => !self.flag(NEGATIVE),
+					"bvc" => !self.flag(OVERFLOW),
+					_ => self.flag(OVERFLOW),
+				};
+
+				if taken {
+					let target = Cpu::address(&operand);
+					self.cycles += if target & 0xFF00 != self.pc & 0xFF00 {
+						2
+					} else {
+						1
+					};
+					self.pc = target;
+				}
+			}
+
+			//Flags
+			"clc" => self.set_flag(CARRY, false),
+			"cld" => self.set_flag(DECIMAL, false),
+			"cli" => self.set_flag(INTERRUPT, false),
+			"clv" => self.set_flag(OVERFLOW, false),
+			"sec" => self.set_flag(CARRY, true),
+			"sed" => self.set_flag(DECIMAL, true),
+			"sei" => self.set_flag(INTERRUPT, true),
+
+			"nop" => {}
+
+			_ => panic!("Unhandled instruction `{}`", mnemonic),
+		}
+
+		Ok(())
+	}
+}
+
+fn read_word(bus: &mut dyn Bus, address: u16) -> u16 {
+	let lo = bus.read(address) as u16;
+	let hi = bus.read(address.wrapping_add(1)) as u16;
+	hi << 8 | lo
+}
+
+//Pointers in zero page wrap around within zero page.
+fn read_zp_word(bus: &mut dyn Bus, address: u8) -> u16 {
+	let lo = bus.read(address as u16) as u16;
+	let hi = bus.read(address.wrapping_add(1) as u16) as u16;
+	hi << 8 | lo
+}
+
+//Base cycle count of an instruction, not including page crossing or branch penalties.
+fn cycles(mnemonic: &str, mode: usize) -> u8 {
+	let read_modify_write = matches!(mnemonic, "asl" | "lsr" | "rol" | "ror" | "inc" | "dec");
+
+	match (mnemonic, mode) {
+		("brk", _) => 7,
+		("jsr", _) | ("rts", _) | ("rti", _) => 6,
+		("pha", _) | ("php", _) => 3,
+		("pla", _) | ("plp", _) => 4,
+		("jmp", ABSOLUTE) => 3,
+		("jmp", INDIRECT) => 5,
+		(_, IMPLIED) | (_, ACCUMULATOR) | (_, IMMEDIATE) | (_, RELATIVE) => 2,
+		(_, ZERO_PAGE) if read_modify_write => 5,
+		(_, ZERO_PAGE) => 3,
+		(_, ZERO_PAGE_X) | (_, ZERO_PAGE_Y) if read_modify_write => 6,
+		(_, ZERO_PAGE_X) | (_, ZERO_PAGE_Y) => 4,
+		(_, ABSOLUTE) if read_modify_write => 6,
+		(_, ABSOLUTE) => 4,
+		(_, ABSOLUTE_X) if read_modify_write => 7,
+		("sta", ABSOLUTE_X) | ("sta", ABSOLUTE_Y) => 5,
+		(_, ABSOLUTE_X) | (_, ABSOLUTE_Y) => 4,
+		(_, INDIRECT_X) => 6,
+		("sta", INDIRECT_Y) => 6,
+		(_, INDIRECT_Y) => 5,
+		_ => 2,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	//Where test programs go: page 2, so a branch or index can easily cross into page 3.
+	const START: u16 = 0x0200;
+
+	//64K of plain RAM.
+	struct Ram(Vec<u8>);
+
+	impl Bus for Ram {
+		fn read(&mut self, address: u16) -> u8 {
+			self.0[address as usize]
+		}
+
+		fn write(&mut self, address: u16, value: u8) {
+			self.0[address as usize] = value;
+		}
+	}
+
+	fn machine(program: &[u8], at: u16) -> (Cpu, Ram) {
+		let mut ram = Ram(vec![0; 0x10000]);
+		ram.0[at as usize..at as usize + program.len()].copy_from_slice(program);
+		let mut cpu = Cpu::new();
+		cpu.pc = at;
+		(cpu, ram)
+	}
+
+	//Run one instruction and return how many cycles it took.
+	fn step(cpu: &mut Cpu, ram: &mut Ram) -> u64 {
+		let before = cpu.cycles;
+		cpu.step(ram).unwrap();
+		cpu.cycles - before
+	}
+
+	//Add or subtract (by the given opcode) with A and the carry flag set as given.
+	fn arithmetic(opcode: u8, decimal: bool, a: u8, value: u8, carry: bool) -> Cpu {
+		let (mut cpu, mut ram) = machine(&[opcode, value], START);
+		cpu.a = a;
+		cpu.set_flag(CARRY, carry);
+		cpu.set_flag(DECIMAL, decimal);
+		step(&mut cpu, &mut ram);
+		cpu
+	}
+
+	//The result, then the C, Z, V and N flags.
+	fn result(cpu: &Cpu) -> (u8, bool, bool, bool, bool) {
+		(
+			cpu.a,
+			cpu.flag(CARRY),
+			cpu.flag(ZERO),
+			cpu.flag(OVERFLOW),
+			cpu.flag(NEGATIVE),
+		)
+	}
+
+	const ADC: u8 = 0x69;
+	const SBC: u8 = 0xE9;
+
+	#[test]
+	fn adc() {
+		let adc = |a, value, carry| result(&arithmetic(ADC, false, a, value, carry));
+		assert_eq!(adc(0x10, 0x20, true), (0x31, false, false, false, false));
+		assert_eq!(adc(0xFF, 0x01, false), (0x00, true, true, false, false));
+		//Two positives making a negative, and two negatives making a positive, overflow.
+		assert_eq!(adc(0x50, 0x50, false), (0xA0, false, false, true, true));
+		assert_eq!(adc(0x80, 0xFF, false), (0x7F, true, false, true, false));
+		//Mixed signs never do.
+		assert_eq!(adc(0x7F, 0x80, true), (0x00, true, true, false, false));
+	}
+
+	#[test]
+	fn sbc() {
+		let sbc = |a, value, carry| result(&arithmetic(SBC, false, a, value, carry));
+		//The carry flag is an inverted borrow.
+		assert_eq!(sbc(0x50, 0x20, true), (0x30, true, false, false, false));
+		assert_eq!(sbc(0x50, 0x20, false), (0x2F, true, false, false, false));
+		assert_eq!(sbc(0x20, 0x20, true), (0x00, true, true, false, false));
+		assert_eq!(sbc(0x50, 0xF0, true), (0x60, false, false, false, false));
+		assert_eq!(sbc(0x50, 0xB0, true), (0xA0, false, false, true, true));
+		assert_eq!(sbc(0xD0, 0x70, true), (0x60, true, false, true, false));
+	}
+
+	#[test]
+	fn decimal() {
+		let adc = |a, value, carry| arithmetic(ADC, true, a, value, carry);
+		let sbc = |a, value, carry| arithmetic(SBC, true, a, value, carry);
+
+		assert_eq!(adc(0x09, 0x01, false).a, 0x10);
+		let cpu = adc(0x58, 0x46, true);
+		assert_eq!((cpu.a, cpu.flag(CARRY)), (0x05, true));
+		//On the NMOS chip, Z comes from the binary sum ($9A), not the decimal one.
+		let cpu = adc(0x99, 0x01, false);
+		assert_eq!(
+			(cpu.a, cpu.flag(CARRY), cpu.flag(ZERO)),
+			(0x00, true, false)
+		);
+
+		let cpu = sbc(0x46, 0x12, true);
+		assert_eq!((cpu.a, cpu.flag(CARRY)), (0x34, true));
+		let cpu = sbc(0x40, 0x13, true);
+		assert_eq!((cpu.a, cpu.flag(CARRY)), (0x27, true));
+		let cpu = sbc(0x12, 0x21, true);
+		assert_eq!((cpu.a, cpu.flag(CARRY)), (0x91, false));
+		let cpu = sbc(0x00, 0x01, false);
+		assert_eq!((cpu.a, cpu.flag(CARRY)), (0x98, false));
+	}
+
+	#[test]
+	fn bit() {
+		//bit $10
+		let (mut cpu, mut ram) = machine(&[0x24, 0x10], START);
+		ram.0[0x10] = 0xC0;
+		cpu.a = 0x01;
+		step(&mut cpu, &mut ram);
+		//N and V come straight from the operand, Z from ANDing it with A, which is left alone.
+		assert!(cpu.flag(NEGATIVE) && cpu.flag(OVERFLOW) && cpu.flag(ZERO));
+		assert_eq!(cpu.a, 0x01);
+
+		let (mut cpu, mut ram) = machine(&[0x24, 0x10], START);
+		ram.0[0x10] = 0x01;
+		cpu.a = 0x01;
+		cpu.status |= NEGATIVE | OVERFLOW;
+		step(&mut cpu, &mut ram);
+		assert!(!cpu.flag(NEGATIVE) && !cpu.flag(OVERFLOW) && !cpu.flag(ZERO));
+	}
+
+	#[test]
+	fn page_crossing() {
+		//lda $02F0,x
+		let (mut cpu, mut ram) = machine(&[0xBD, 0xF0, 0x02], START);
+		cpu.x = 0x0F;
+		assert_eq!(step(&mut cpu, &mut ram), 4);
+		let (mut cpu, mut ram) = machine(&[0xBD, 0xF0, 0x02], START);
+		cpu.x = 0x10;
+		assert_eq!(step(&mut cpu, &mut ram), 5);
+
+		//Stores always take the extra cycle, whether or not they cross.
+		//sta $02F0,x
+		let (mut cpu, mut ram) = machine(&[0x9D, 0xF0, 0x02], START);
+		assert_eq!(step(&mut cpu, &mut ram), 5);
+		cpu.pc = START;
+		cpu.x = 0x10;
+		assert_eq!(step(&mut cpu, &mut ram), 5);
+
+		//lda ($10),y
+		let (mut cpu, mut ram) = machine(&[0xB1, 0x10], START);
+		ram.0[0x10..0x12].copy_from_slice(&[0xFF, 0x02]);
+		assert_eq!(step(&mut cpu, &mut ram), 5);
+		cpu.pc = START;
+		cpu.y = 1;
+		assert_eq!(step(&mut cpu, &mut ram), 6);
+		//sta ($10),y
+		ram.0[START as usize] = 0x91;
+		cpu.pc = START;
+		assert_eq!(step(&mut cpu, &mut ram), 6);
+		assert_eq!(ram.0[0x0300], cpu.a);
+	}
+
+	#[test]
+	fn branches() {
+		//beq +$10
+		let beq = [0xF0, 0x10];
+
+		let (mut cpu, mut ram) = machine(&beq, START);
+		assert_eq!(step(&mut cpu, &mut ram), 2);
+		assert_eq!(cpu.pc, START + 2);
+
+		let (mut cpu, mut ram) = machine(&beq, START);
+		cpu.status |= ZERO;
+		assert_eq!(step(&mut cpu, &mut ram), 3);
+		assert_eq!(cpu.pc, START + 0x12);
+
+		//The page that counts is the one after the branch, not the one it starts on.
+		let (mut cpu, mut ram) = machine(&beq, 0x02EE);
+		cpu.status |= ZERO;
+		assert_eq!(step(&mut cpu, &mut ram), 4);
+		assert_eq!(cpu.pc, 0x0300);
+		let (mut cpu, mut ram) = machine(&beq, 0x02FE);
+		cpu.status |= ZERO;
+		assert_eq!(step(&mut cpu, &mut ram), 3);
+		assert_eq!(cpu.pc, 0x0310);
+
+		//bne -$10, backwards into the previous page.
+		let (mut cpu, mut ram) = machine(&[0xD0, 0xF0], START);
+		assert_eq!(step(&mut cpu, &mut ram), 4);
+		assert_eq!(cpu.pc, 0x01F2);
+	}
+
+	#[test]
+	fn indirect_jump() {
+		//jmp ($02FF) takes the high byte from $0200, not $0300.
+		let (mut cpu, mut ram) = machine(&[0x6C, 0xFF, 0x02], 0x0400);
+		ram.0[0x02FF] = 0x34;
+		ram.0[0x0200] = 0x12;
+		ram.0[0x0300] = 0x56;
+		assert_eq!(step(&mut cpu, &mut ram), 5);
+		assert_eq!(cpu.pc, 0x1234);
+
+		//Away from the end of a page it's an ordinary pointer.
+		let (mut cpu, mut ram) = machine(&[0x6C, 0xFE, 0x02], 0x0400);
+		ram.0[0x02FE] = 0x34;
+		ram.0[0x02FF] = 0x56;
+		step(&mut cpu, &mut ram);
+		assert_eq!(cpu.pc, 0x5634);
+	}
+
+	#[test]
+	fn brk() {
+		//brk, a padding byte, then nop. The handler at $0400 is just rti.
+		let (mut cpu, mut ram) = machine(&[0x00, 0xFF, 0xEA], START);
+		ram.0[0x0400] = 0x40;
+		ram.0[0xFFFE..].copy_from_slice(&[0x00, 0x04]);
+		cpu.status |= CARRY;
+		cpu.status &= !INTERRUPT;
+
+		assert_eq!(step(&mut cpu, &mut ram), 7);
+		assert_eq!(cpu.pc, 0x0400);
+		assert_eq!(cpu.sp, 0xFA);
+		assert!(cpu.flag(INTERRUPT));
+		//The return address skips the padding byte, and the pushed status has B set.
+		assert_eq!(ram.0[0x01FD], 0x02);
+		assert_eq!(ram.0[0x01FC], 0x02);
+		assert_eq!(ram.0[0x01FB], CARRY | BREAK | UNUSED);
+
+		assert_eq!(step(&mut cpu, &mut ram), 6);
+		assert_eq!(cpu.pc, START + 2);
+		assert_eq!(cpu.sp, 0xFD);
+		assert_eq!(cpu.status, CARRY | UNUSED);
+	}
+
+	#[test]
+	fn illegal_opcode() {
+		let (mut cpu, mut ram) = machine(&[0x02], START);
+		assert_eq!(
+			cpu.step(&mut ram),
+			Err("Illegal opcode $02 at $0200".to_string())
+		);
+	}
+}
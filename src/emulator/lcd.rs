@@ -0,0 +1,86 @@
+//An HD44780 LCD on a 6522 VIA, wired as on the breadboard computer: data on port B, and the E,
+//RW and RS lines on the top three pins of port A. Only what the breadboard target's runtime uses
+//is modelled: the LCD never reports itself busy, and instructions other than clearing the display
+//and moving the cursor are ignored.
+
+//VIA registers, as offsets from where it's mapped.
+const PORTB: u16 = 0;
+const PORTA: u16 = 1;
+const DDRB: u16 = 2;
+const DDRA: u16 = 3;
+//Port A control lines.
+const E: u8 = 0b1000_0000;
+const RW: u8 = 0b0100_0000;
+const RS: u8 = 0b0010_0000;
+//LCD instructions.
+const CLEAR: u8 = 0b0000_0001;
+const SET_ADDRESS: u8 = 0b1000_0000;
+
+/// The VIA and the LCD on it.
+#[derive(Default)]
+pub struct Lcd {
+	port_a: u8,
+	port_b: u8,
+	ddr_a: u8,
+	ddr_b: u8,
+	//Where the next character goes on the display.
+	address: u8,
+	//Whether the cursor's line has anything on it yet.
+	line_used: bool,
+}
+
+impl Lcd {
+	pub fn read(&self, register: u16) -> u8 {
+		match register {
+			//With RW and E high, the LCD drives the data lines with the busy flag (always clear)
+			//and its address.
+			PORTB if self.port_a & (RW | E) == RW | E => self.address & 0x7F,
+			PORTB => self.port_b,
+			PORTA => self.port_a,
+			DDRB => self.ddr_b,
+			DDRA => self.ddr_a,
+			_ => 0,
+		}
+	}
+
+	/// Characters shown on the display go to `output`, with a line break each time the cursor
+	/// leaves a line that has something on it.
+	pub fn write(&mut self, register: u16, value: u8, output: &mut Vec<u8>) {
+		match register {
+			PORTB => self.port_b = value,
+			PORTA => {
+				//The LCD takes what's on the data lines when E goes low.
+				let latched = self.port_a & E != 0 && value & E == 0;
+				self.port_a = value;
+				if latched && value & RW == 0 {
+					if value & RS == 0 {
+						self.instruction(self.port_b, output);
+					} else {
+						output.push(self.port_b);
+						self.address = self.address.wrapping_add(1);
+						self.line_used = true;
+					}
+				}
+			}
+			DDRB => self.ddr_b = value,
+			DDRA => self.ddr_a = value,
+			_ => {}
+		}
+	}
+
+	fn instruction(&mut self, instruction: u8, output: &mut Vec<u8>) {
+		let address = if instruction & SET_ADDRESS != 0 {
+			instruction & !SET_ADDRESS
+		} else if instruction == CLEAR {
+			0
+		} else {
+			return;
+		};
+
+		if self.line_used {
+			output.push(b'\n');
+		}
+		self.address = address;
+		self.line_used = false;
+	}
+}
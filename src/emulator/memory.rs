@@ -0,0 +1,99 @@
+use super::cpu::Bus;
+use super::lcd::Lcd;
+
+/// What a range of addresses is connected to.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Device {
+	Ram,
+	/// Can only be written by loading an image; writes from the CPU are ignored, like real ROM.
+	Rom,
+	/// Every byte written here is taken as a character of output. Reads give zero.
+	Output,
+	/// A 6522 VIA with an LCD on it, as on the breadboard computer. What the LCD shows is output.
+	Lcd,
+}
+
+/// An inclusive range of addresses and what they're connected to.
+#[derive(Clone, Copy, Debug)]
+pub struct Region {
+	pub start: u16,
+	pub end: u16,
+	pub device: Device,
+}
+
+/// The whole 64K address space, as seen by the CPU.
+/// Anything not covered by a region reads as $FF (nothing drives the bus) and ignores writes.
+pub struct MemoryMap {
+	regions: Vec<Region>,
+	bytes: Vec<u8>,
+	/// Everything written to output devices so far.
+	pub output: Vec<u8>,
+	lcd: Lcd,
+}
+
+impl MemoryMap {
+	pub fn new(regions: Vec<Region>) -> MemoryMap {
+		MemoryMap {
+			regions,
+			bytes: vec![0; 0x10000],
+			output: vec![],
+			lcd: Lcd::default(),
+		}
+	}
+
+	/// Copy data into memory, bypassing any write protection. This is how ROM gets its contents.
+	pub fn load(&mut self, address: u16, data: &[u8]) -> Result<(), String> {
+		let end = address as usize + data.len();
+		if end > self.bytes.len() {
+			return Err(format!(
+				"Image of {} bytes does not fit in memory at ${:04X}",
+				data.len(),
+				address
+			));
+		}
+		self.bytes[address as usize..end].copy_from_slice(data);
+		Ok(())
+	}
+
+	/// Read a little-endian word straight out of memory, without going through any device.
+	pub fn word(&self, address: u16) -> u16 {
+		let high = self.bytes[address.wrapping_add(1) as usize] as u16;
+		high << 8 | self.bytes[address as usize] as u16
+	}
+
+	fn region(&self, address: u16) -> Option<Region> {
+		self.regions
+			.iter()
+			.find(|region| region.start <= address && address <= region.end)
+			.copied()
+	}
+}
+
+impl Bus for MemoryMap {
+	fn read(&mut self, address: u16) -> u8 {
+		let region = match self.region(address) {
+			Some(region) => region,
+			None => return 0xFF,
+		};
+		match region.device {
+			Device::Ram | Device::Rom => self.bytes[address as usize],
+			Device::Output => 0,
+			Device::Lcd => self.lcd.read(address - region.start),
+		}
+	}
+
+	fn write(&mut self, address: u16, value: u8) {
+		let region = match self.region(address) {
+			Some(region) => region,
+			None => return,
+		};
+		match region.device {
+			Device::Ram => self.bytes[address as usize] = value,
+			Device::Output => self.output.push(value),
+			Device::Lcd => self
+				.lcd
+				.write(address - region.start, value, &mut self.output),
+			Device::Rom => {}
+		}
+	}
+}
@@ -1,10 +1,15 @@
+use crate::codegen::peephole;
 use std::path::PathBuf;
+use structopt::clap::{AppSettings, Error, ErrorKind};
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
 #[structopt(
 	name = "Aglet Compiler",
-	about = "A modern language for an old system;\nCompiles Aglet source to 6502 assembly."
+	about = "A modern language for an old system;\nCompiles Aglet source to 6502 assembly.",
+	//Only `test` itself is the subcommand. Otherwise, clap takes an input file whose name starts
+	//like `test` for a misspelling of it.
+	setting = AppSettings::AllowExternalSubcommands
 )]
 pub struct Options {
 	/// Prints the abstract syntax tree
@@ -12,27 +17,179 @@ pub struct Options {
 	#[structopt(long)]
 	pub ast: bool,
 
+	/// Prints the intermediate representation
+	#[cfg(debug_assertions)]
+	#[structopt(long)]
+	pub ir: bool,
+
 	/// Suppress warnings
-	#[structopt(long, short)]
+	#[structopt(long, short, global = true)]
 	pub warn_suppress: bool,
 
 	/// Output raw binary instead of assembly
 	#[structopt(long, short)]
 	pub binary: bool,
 
+	/// Run the program on the built-in 6502 emulator, printing what it prints, instead of writing it out
+	#[structopt(long, short)]
+	pub run: bool,
+
+	/// Run every `test funk` on the built-in 6502 emulator and report which pass.
+	/// `aglet test FILE` does the same
+	#[structopt(long, short)]
+	pub test: bool,
+
+	/// Optimization level: 0 (none), 1 (safe, quick rewrites) or 2 (everything)
+	#[structopt(short = "O", value_name = "LEVEL", default_value = "1", global = true, parse(try_from_str = parse_opt_level))]
+	pub opt_level: u8,
+
+	/// What to favour when an optimization makes code faster but bigger: `size` or `speed`
+	#[structopt(long, value_name = "GOAL", default_value = "size", global = true, parse(try_from_str = parse_goal))]
+	pub optimize_for: Goal,
+
+	/// The computer to build for: a built-in target (`emulator`), or a target file describing a board
+	#[structopt(long, value_name = "TARGET", default_value = "emulator", global = true)]
+	pub target: String,
+
+	/// Zero page addresses the compiler must not use, e.g. `0x00-0x1F` or `$FF`.
+	/// Can be given more than once
+	#[structopt(long, value_name = "RANGE", number_of_values = 1, global = true, parse(try_from_str = parse_zp_range))]
+	pub zp_reserve: Vec<(u8, u8)>,
+
+	/// Put whatever doesn't fit in zero page in slower memory, instead of stopping with an error
+	#[structopt(long, global = true)]
+	pub zp_spill: bool,
+
+	/// A peephole optimization to leave out, by name (e.g. `dead-store`).
+	/// Can be given more than once
+	#[structopt(long, value_name = "RULE", number_of_values = 1, global = true, parse(try_from_str = parse_peephole_rule))]
+	pub no_peephole: Vec<String>,
+
 	/// Output detailed info in an easy-to-parse format
 	#[structopt(long)]
 	pub language_server: bool,
 
 	/// The input file
+	//Always there once `read` has returned.
 	#[structopt(parse(from_os_str))]
-	pub input: PathBuf,
+	pub input: Option<PathBuf>,
+
+	#[structopt(subcommand)]
+	pub command: Option<Command>,
 
-	/// The output file
+	/// The output file (not needed with `--run` or `--test`)
 	#[structopt(parse(from_os_str))]
-	pub output: PathBuf,
+	pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum Command {
+	/// Run every `test funk` on the built-in 6502 emulator and report which pass
+	Test {
+		/// The input file
+		#[structopt(parse(from_os_str))]
+		input: PathBuf,
+	},
+	//Anything after both files. Allowing it is what keeps clap from guessing at subcommands.
+	#[structopt(external_subcommand)]
+	Other(Vec<String>),
+}
+
+/// What to favour when an optimization is a trade-off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Goal {
+	Size,
+	Speed,
 }
 
 pub fn read() -> Options {
-	return Options::from_args();
+	let mut options = Options::from_args();
+
+	//`aglet test FILE` reads better than `aglet --test FILE`, and does the same.
+	match options.command.take() {
+		Some(Command::Test { input }) => {
+			options.test = true;
+			options.input = Some(input);
+		}
+		Some(Command::Other(arguments)) => Error::with_description(
+			&format!("Unexpected argument `{}`", arguments[0]),
+			ErrorKind::UnknownArgument,
+		)
+		.exit(),
+		None => {}
+	}
+	//Clap can't tell which positional arguments are needed when there's a subcommand too.
+	let missing = if options.input.is_none() {
+		"The input file was not given"
+	} else if options.output.is_none() && !options.run && !options.test {
+		"The output file was not given (it's not needed with `--run` or `test`)"
+	} else {
+		return options;
+	};
+	Error::with_description(missing, ErrorKind::MissingRequiredArgument).exit();
+}
+
+fn parse_goal(text: &str) -> Result<Goal, String> {
+	match text {
+		"size" => Ok(Goal::Size),
+		"speed" => Ok(Goal::Speed),
+		_ => Err(format!("`{}` is not a goal (`size` or `speed`)", text)),
+	}
+}
+
+fn parse_peephole_rule(text: &str) -> Result<String, String> {
+	if peephole::RULES.iter().any(|(name, _, _)| *name == text) {
+		return Ok(text.to_string());
+	}
+	Err(format!(
+		"`{}` is not a peephole optimization ({})",
+		text,
+		peephole::RULES
+			.iter()
+			.map(|(name, _, _)| format!("`{}`", name))
+			.collect::<Vec<_>>()
+			.join(", ")
+	))
+}
+
+fn parse_opt_level(text: &str) -> Result<u8, String> {
+	match text.parse::<u8>() {
+		Ok(level) if level <= 2 => Ok(level),
+		_ => Err(format!(
+			"`{}` is not an optimization level (0, 1 or 2)",
+			text
+		)),
+	}
+}
+
+//Parse a zero page address like `0x1F`, `$1F` or `31`.
+fn parse_zp_address(text: &str) -> Result<u8, String> {
+	let text = text.trim();
+	let value = if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix('$')) {
+		u16::from_str_radix(hex, 16)
+	} else {
+		text.parse::<u16>()
+	};
+
+	match value {
+		Ok(value) if value <= 0xFF => Ok(value as u8),
+		Ok(_) => Err(format!("`{}` is not in zero page", text)),
+		Err(_) => Err(format!("`{}` is not a valid address", text)),
+	}
+}
+
+//Parse an inclusive range of zero page addresses like `0x00-0x1F`, or a single address.
+pub fn parse_zp_range(text: &str) -> Result<(u8, u8), String> {
+	let (first, last) = match text.split_once('-') {
+		Some((first, last)) => (parse_zp_address(first)?, parse_zp_address(last)?),
+		None => {
+			let address = parse_zp_address(text)?;
+			(address, address)
+		}
+	};
+
+	if first > last {
+		return Err(format!("`{}` is an empty range", text));
+	}
+	Ok((first, last))
 }
@@ -0,0 +1,396 @@
+//! A lowered form of the program that sits between the AST and the 6502 backend.
+//!
+//! Each function is a list of basic blocks of three-address instructions. Every variable and
+//! intermediate value is a numbered temporary, which can be assigned more than once (this is not SSA).
+//! Blocks end in exactly one terminator, and control only enters a block at the top.
+
+use crate::lexer::Span;
+use std::fmt;
+
+mod builder;
+mod expression;
+mod inline;
+mod optimize;
+mod statement;
+mod strength;
+
+pub use builder::lower;
+pub use optimize::optimize;
+
+/// A temporary value, numbered within its function.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub struct Temp(pub usize);
+
+/// A basic block, numbered within its function.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub struct BlockId(pub usize);
+
+/// How big a temporary is.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Width {
+	/// 8 bits, e.g. the result of a comparison.
+	Byte,
+	/// 16 bits, e.g. an `int`.
+	Word,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Operand {
+	Const(i16),
+	Temp(Temp),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UnaryOp {
+	/// Two's complement negation.
+	Neg,
+	/// Zero-extend a byte into a word.
+	Extend,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BinaryOp {
+	Add,
+	Sub,
+	Mul,
+	Div,
+	Rem,
+	/// Shift left by a constant number of bits.
+	ShiftLeft,
+	/// Shift right by a constant number of bits, copying the sign bit in from the left.
+	ShiftRight,
+	/// Bitwise and.
+	And,
+	/// Compare two words, producing a byte that is 1 if the comparison holds and 0 otherwise.
+	Compare(Comparison),
+}
+
+impl UnaryOp {
+	/// Work out the result on a known value, exactly as the generated code would.
+	pub fn apply(self, value: i16) -> i16 {
+		match self {
+			UnaryOp::Neg => value.wrapping_neg(),
+			UnaryOp::Extend => value,
+		}
+	}
+}
+
+impl BinaryOp {
+	/// Work out the result on known values, exactly as the generated code would.
+	/// Arithmetic wraps around, and dividing by zero gives -1 with a remainder of `a`.
+	pub fn apply(self, a: i16, b: i16) -> i16 {
+		match self {
+			BinaryOp::Add => a.wrapping_add(b),
+			BinaryOp::Sub => a.wrapping_sub(b),
+			BinaryOp::Mul => a.wrapping_mul(b),
+			BinaryOp::Div if b == 0 => -1,
+			BinaryOp::Div => a.wrapping_div(b),
+			BinaryOp::Rem if b == 0 => a,
+			BinaryOp::Rem => a.wrapping_rem(b),
+			BinaryOp::ShiftLeft => a.wrapping_shl(b as u32),
+			BinaryOp::ShiftRight => a.wrapping_shr(b as u32),
+			BinaryOp::And => a & b,
+			BinaryOp::Compare(comparison) => comparison.holds(a, b) as i16,
+		}
+	}
+}
+
+/// Signed comparison of two words.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Comparison {
+	Less,
+	LessOrEqual,
+	Greater,
+	GreaterOrEqual,
+	Equal,
+	NotEqual,
+}
+
+impl Comparison {
+	/// Whether the comparison holds between two known values.
+	pub fn holds(self, a: i16, b: i16) -> bool {
+		match self {
+			Comparison::Less => a < b,
+			Comparison::LessOrEqual => a <= b,
+			Comparison::Greater => a > b,
+			Comparison::GreaterOrEqual => a >= b,
+			Comparison::Equal => a == b,
+			Comparison::NotEqual => a != b,
+		}
+	}
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Inst {
+	/// dest = src
+	Copy(Temp, Operand),
+	/// dest = op src
+	Unary(Temp, UnaryOp, Operand),
+	/// dest = a op b
+	Binary(Temp, BinaryOp, Operand, Operand),
+	/// dest = function(args...). Builtins like `print` are called this way too.
+	/// `assert` and `assert_eq` take two more arguments, the start and end of the assertion in
+	/// the source, so a failure can point at it.
+	Call(Option<Temp>, String, Vec<Operand>),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Terminator {
+	Jump(BlockId),
+	/// Go to the first block if the comparison holds, otherwise the second.
+	Branch(Comparison, Operand, Operand, BlockId, BlockId),
+	Return(Option<Operand>),
+}
+
+#[derive(Clone, Debug)]
+pub struct Block {
+	pub insts: Vec<Inst>,
+	pub terminator: Terminator,
+}
+
+/// Everything the IR knows about a temporary.
+#[derive(Clone, Debug)]
+pub struct TempInfo {
+	pub width: Width,
+	/// Name of the variable this holds, if it's not an intermediate value.
+	pub name: Option<String>,
+}
+
+/// Whether calls to a function should be replaced by a copy of its body.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Inline {
+	/// Let the optimizer decide.
+	Auto,
+	/// `@inline`
+	Always,
+	/// `@noinline`
+	Never,
+}
+
+#[derive(Clone, Debug)]
+pub struct Function {
+	pub name: String,
+	pub span: Span,
+	/// Whether every return has to give back a value.
+	pub returns_value: bool,
+	pub inline: Inline,
+	/// Whether this is a test, which only runs when testing.
+	pub test: bool,
+	/// Parameters are always the first temporaries.
+	pub params: Vec<Temp>,
+	pub temps: Vec<TempInfo>,
+	/// The first block is the entry point.
+	pub blocks: Vec<Block>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Module {
+	/// Every function, in the order it was declared.
+	pub functions: Vec<Function>,
+	/// The function that runs at start-up. Everything else only runs if this calls it.
+	pub entry: String,
+}
+
+impl Module {
+	/// Build the program around another entry point (a test, say). Tests other than the entry
+	/// point can't run, so they're left out.
+	pub fn start_at(&mut self, entry: &str) {
+		self.entry = entry.to_string();
+		self.functions
+			.retain(|function| !function.test || function.name == entry);
+	}
+
+	pub fn function(&self, name: &str) -> Option<&Function> {
+		self.functions.iter().find(|function| function.name == name)
+	}
+}
+
+impl Inst {
+	/// The temporary this instruction writes to, if any.
+	pub fn dest(&self) -> Option<Temp> {
+		match self {
+			Inst::Copy(dest, _) | Inst::Unary(dest, _, _) | Inst::Binary(dest, _, _, _) => {
+				Some(*dest)
+			}
+			Inst::Call(dest, _, _) => *dest,
+		}
+	}
+
+	/// Everything this instruction reads.
+	pub fn operands(&self) -> Vec<Operand> {
+		match self {
+			Inst::Copy(_, src) | Inst::Unary(_, _, src) => vec![*src],
+			Inst::Binary(_, _, a, b) => vec![*a, *b],
+			Inst::Call(_, _, args) => args.clone(),
+		}
+	}
+
+	pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+		match self {
+			Inst::Copy(_, src) | Inst::Unary(_, _, src) => vec![src],
+			Inst::Binary(_, _, a, b) => vec![a, b],
+			Inst::Call(_, _, args) => args.iter_mut().collect(),
+		}
+	}
+}
+
+impl Terminator {
+	/// Everything this terminator reads.
+	pub fn operands(&self) -> Vec<Operand> {
+		match self {
+			Terminator::Jump(_) | Terminator::Return(None) => vec![],
+			Terminator::Branch(_, a, b, _, _) => vec![*a, *b],
+			Terminator::Return(Some(value)) => vec![*value],
+		}
+	}
+
+	pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+		match self {
+			Terminator::Jump(_) | Terminator::Return(None) => vec![],
+			Terminator::Branch(_, a, b, _, _) => vec![a, b],
+			Terminator::Return(Some(value)) => vec![value],
+		}
+	}
+
+	/// Blocks that control can go to next.
+	pub fn successors(&self) -> Vec<BlockId> {
+		match self {
+			Terminator::Jump(target) => vec![*target],
+			Terminator::Branch(_, _, _, yes, no) => vec![*yes, *no],
+			Terminator::Return(_) => vec![],
+		}
+	}
+
+	pub fn successors_mut(&mut self) -> Vec<&mut BlockId> {
+		match self {
+			Terminator::Jump(target) => vec![target],
+			Terminator::Branch(_, _, _, yes, no) => vec![yes, no],
+			Terminator::Return(_) => vec![],
+		}
+	}
+}
+
+impl fmt::Display for Operand {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Operand::Const(value) => write!(f, "{}", value),
+			Operand::Temp(temp) => write!(f, "{}", temp),
+		}
+	}
+}
+
+impl fmt::Display for Temp {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "t{}", self.0)
+	}
+}
+
+impl fmt::Display for BlockId {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "b{}", self.0)
+	}
+}
+
+impl fmt::Display for Comparison {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let text = match self {
+			Comparison::Less => "<",
+			Comparison::LessOrEqual => "<=",
+			Comparison::Greater => ">",
+			Comparison::GreaterOrEqual => ">=",
+			Comparison::Equal => "==",
+			Comparison::NotEqual => "!=",
+		};
+		write!(f, "{}", text)
+	}
+}
+
+impl fmt::Display for Inst {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Inst::Copy(dest, src) => write!(f, "{} = {}", dest, src),
+			Inst::Unary(dest, UnaryOp::Neg, src) => write!(f, "{} = -{}", dest, src),
+			Inst::Unary(dest, UnaryOp::Extend, src) => write!(f, "{} = extend {}", dest, src),
+			Inst::Binary(dest, op, a, b) => {
+				let op = match op {
+					BinaryOp::Add => "+".to_string(),
+					BinaryOp::Sub => "-".to_string(),
+					BinaryOp::Mul => "*".to_string(),
+					BinaryOp::Div => "/".to_string(),
+					BinaryOp::Rem => "%".to_string(),
+					BinaryOp::ShiftLeft => "<<".to_string(),
+					BinaryOp::ShiftRight => ">>".to_string(),
+					BinaryOp::And => "&".to_string(),
+					BinaryOp::Compare(comparison) => comparison.to_string(),
+				};
+				write!(f, "{} = {} {} {}", dest, a, op, b)
+			}
+			Inst::Call(dest, name, args) => {
+				let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+				match dest {
+					Some(dest) => write!(f, "{} = {}({})", dest, name, args.join(", ")),
+					None => write!(f, "{}({})", name, args.join(", ")),
+				}
+			}
+		}
+	}
+}
+
+impl fmt::Display for Terminator {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Terminator::Jump(target) => write!(f, "jump {}", target),
+			Terminator::Branch(comparison, a, b, yes, no) => {
+				write!(f, "if {} {} {} then {} else {}", a, comparison, b, yes, no)
+			}
+			Terminator::Return(None) => write!(f, "ret"),
+			Terminator::Return(Some(value)) => write!(f, "ret {}", value),
+		}
+	}
+}
+
+impl fmt::Display for Function {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let params: Vec<String> = self.params.iter().map(|param| param.to_string()).collect();
+		if self.test {
+			write!(f, "test ")?;
+		}
+		match self.inline {
+			Inline::Auto => {}
+			Inline::Always => write!(f, "@inline ")?,
+			Inline::Never => write!(f, "@noinline ")?,
+		}
+		writeln!(f, "funk {}({}) {{", self.name, params.join(", "))?;
+
+		for (index, temp) in self.temps.iter().enumerate() {
+			let width = match temp.width {
+				Width::Byte => "byte",
+				Width::Word => "word",
+			};
+			write!(f, "\t{}: {}", Temp(index), width)?;
+			match &temp.name {
+				Some(name) => writeln!(f, " ({})", name)?,
+				None => writeln!(f)?,
+			}
+		}
+
+		for (index, block) in self.blocks.iter().enumerate() {
+			writeln!(f, "{}:", BlockId(index))?;
+			for inst in &block.insts {
+				writeln!(f, "\t{}", inst)?;
+			}
+			writeln!(f, "\t{}", block.terminator)?;
+		}
+
+		writeln!(f, "}}")
+	}
+}
+
+impl fmt::Display for Module {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		for function in &self.functions {
+			writeln!(f, "{}", function)?;
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,199 @@
+use super::*;
+use crate::parser::ast::Program;
+use crate::parser::ast::Stmt::FuncDecl;
+use std::collections::{HashMap, HashSet};
+
+/// Convert a program that has passed semantic analysis into IR.
+pub fn lower(ast: &Program) -> Module {
+	let mut functions = vec![];
+
+	//Builtins don't return anything, so only user-defined functions can.
+	let mut returns_value = HashSet::new();
+	for stmt in &ast.stmts {
+		if let FuncDecl(name, _, return_type, _, _, _) = &stmt.node {
+			if return_type.value != "void" {
+				returns_value.insert(name.value.clone());
+			}
+		}
+	}
+
+	for stmt in &ast.stmts {
+		if let FuncDecl(name, params, _return_type, body, attributes, kind) = &stmt.node {
+			let mut builder = Builder::new(&name.value, name.span, &returns_value);
+			//Semantic analysis has already complained about any kind other than `test`.
+			builder.function.test = kind.is_some();
+
+			//Semantic analysis has already complained about anything that isn't one of these.
+			for attribute in attributes.iter() {
+				match attribute.value.as_str() {
+					"inline" => builder.function.inline = Inline::Always,
+					"noinline" => builder.function.inline = Inline::Never,
+					_ => {}
+				}
+			}
+
+			//Parameters come first, in order.
+			for param in params.iter() {
+				let temp = builder.variable(&param.name.value);
+				builder.function.params.push(temp);
+				builder.set_variable(&param.name.value, temp);
+			}
+
+			body.lower(&mut builder);
+			functions.push(builder.finish());
+		}
+	}
+
+	Module {
+		functions,
+		entry: "main".to_string(),
+	}
+}
+
+/// Keeps track of where we are while lowering a function.
+pub struct Builder<'a> {
+	pub function: Function,
+	returns_value: &'a HashSet<String>,
+	//The block that instructions are being added to
+	current: BlockId,
+	scopes: Vec<HashMap<String, Temp>>,
+	//Where `continue` and `break` go in each enclosing loop
+	loops: Vec<(BlockId, BlockId)>,
+}
+
+impl<'a> Builder<'a> {
+	fn new(name: &str, span: Span, returns_value: &'a HashSet<String>) -> Builder<'a> {
+		let entry = Block {
+			insts: vec![],
+			terminator: Terminator::Return(None),
+		};
+
+		Builder {
+			function: Function {
+				name: name.to_string(),
+				span,
+				returns_value: returns_value.contains(name),
+				inline: Inline::Auto,
+				test: false,
+				params: vec![],
+				temps: vec![],
+				blocks: vec![entry],
+			},
+			returns_value,
+			current: BlockId(0),
+			scopes: vec![HashMap::new()],
+			loops: vec![],
+		}
+	}
+
+	fn finish(mut self) -> Function {
+		//Falling off the end of the function returns nothing.
+		self.function.blocks[self.current.0].terminator = Terminator::Return(None);
+		self.function
+	}
+
+	/// Whether calling the given function produces a value.
+	pub fn returns_value(&self, function: &str) -> bool {
+		self.returns_value.contains(function)
+	}
+
+	pub fn emit(&mut self, inst: Inst) {
+		self.function.blocks[self.current.0].insts.push(inst);
+	}
+
+	/// Create a new, empty block. It's not reachable until something jumps to it.
+	pub fn new_block(&mut self) -> BlockId {
+		self.function.blocks.push(Block {
+			insts: vec![],
+			terminator: Terminator::Return(None),
+		});
+		BlockId(self.function.blocks.len() - 1)
+	}
+
+	/// Start a loop that exits to the given block, returning new blocks for its head and body.
+	pub fn enter_loop(&mut self, end: BlockId) -> (BlockId, BlockId) {
+		let head = self.new_block();
+		let body = self.new_block();
+		self.loops.push((head, end));
+		(head, body)
+	}
+
+	pub fn exit_loop(&mut self) {
+		self.loops.pop();
+	}
+
+	/// Where `continue` and `break` go in the innermost loop.
+	pub fn current_loop(&self) -> (BlockId, BlockId) {
+		//Semantic analysis guarantees we're inside a loop.
+		*self.loops.last().unwrap()
+	}
+
+	/// End the current block. Anything emitted after this goes into a new block,
+	/// which is unreachable unless something else jumps to it.
+	pub fn terminate(&mut self, terminator: Terminator) {
+		self.function.blocks[self.current.0].terminator = terminator;
+		self.current = self.new_block();
+	}
+
+	/// Start adding instructions to the given block.
+	/// The current block should already have been terminated.
+	pub fn switch_to(&mut self, block: BlockId) {
+		//The current block is the empty one left over by `terminate()`, so it can go.
+		if self.current.0 == self.function.blocks.len() - 1
+			&& self.function.blocks[self.current.0].insts.is_empty()
+			&& block != self.current
+		{
+			self.function.blocks.pop();
+		}
+		self.current = block;
+	}
+
+	fn new_temp(&mut self, width: Width, name: Option<&str>) -> Temp {
+		self.function.temps.push(TempInfo {
+			width,
+			name: name.map(|name| name.to_string()),
+		});
+		Temp(self.function.temps.len() - 1)
+	}
+
+	/// A new intermediate value.
+	pub fn temp(&mut self, width: Width) -> Temp {
+		self.new_temp(width, None)
+	}
+
+	/// Storage for a variable that will be declared in the current scope.
+	pub fn variable(&mut self, name: &str) -> Temp {
+		self.new_temp(Width::Word, Some(name))
+	}
+
+	/// Whether a temporary holds a variable, rather than an intermediate value.
+	pub fn is_variable(&self, temp: Temp) -> bool {
+		self.function.temps[temp.0].name.is_some()
+	}
+
+	pub fn set_variable(&mut self, name: &str, temp: Temp) {
+		self.scopes
+			.last_mut()
+			.unwrap()
+			.insert(name.to_string(), temp);
+	}
+
+	pub fn get_variable(&self, name: &String) -> Temp {
+		for scope in self.scopes.iter().rev() {
+			if let Some(temp) = scope.get(name) {
+				return *temp;
+			}
+		}
+
+		//Semantic analysis guarantees this will never happen
+		panic!("COMPILER BUG: Use of undeclared variable `{}`", name);
+	}
+
+	pub fn push_scope(&mut self) {
+		self.scopes.push(HashMap::new());
+	}
+
+	pub fn pop_scope(&mut self) {
+		self.scopes.pop();
+	}
+}
@@ -0,0 +1,185 @@
+use super::builder::Builder;
+use super::*;
+use crate::parser::ast::Expr::*;
+use crate::parser::ast::Expression;
+
+impl Expression {
+	/// Lower this expression, returning where its value ends up.
+	pub fn lower(&self, builder: &mut Builder) -> Operand {
+		match &self.node {
+			FuncCall(function, arguments) => match &function.node {
+				Var(name) => {
+					let mut args = lower_all(builder, &arguments.iter().collect::<Vec<_>>());
+					if name == "assert" || name == "assert_eq" {
+						args.push(Operand::Const(self.span.lo as i16));
+						args.push(Operand::Const(self.span.hi as i16));
+					}
+
+					if builder.returns_value(name) {
+						let dest = builder.temp(Width::Word);
+						builder.emit(Inst::Call(Some(dest), name.clone(), args));
+						Operand::Temp(dest)
+					} else {
+						//Semantic analysis guarantees the result of a `void` function is never used.
+						builder.emit(Inst::Call(None, name.clone(), args));
+						Operand::Const(0)
+					}
+				}
+				_ => {
+					panic!("COMPILER BUG: Invalid node in function call!");
+				} // Semantic analysis guarantees this will never happen
+			},
+
+			//Semantic analysis guarantees that literals fit in 16 bits.
+			Integer(value) => Operand::Const(*value as i16),
+
+			Var(name) => Operand::Temp(builder.get_variable(name)),
+
+			Neg(expr) => match expr.node {
+				//Handle this separately, since 32768 by itself doesn't fit in 16 bits.
+				Integer(value) => Operand::Const((-value) as i16),
+				_ => {
+					let src = expr.lower(builder);
+					let dest = builder.temp(Width::Word);
+					builder.emit(Inst::Unary(dest, UnaryOp::Neg, src));
+					Operand::Temp(dest)
+				}
+			},
+
+			Add(a, b) => binary(builder, BinaryOp::Add, a, b),
+			Sub(a, b) => binary(builder, BinaryOp::Sub, a, b),
+			Mult(a, b) => binary(builder, BinaryOp::Mul, a, b),
+			Div(a, b) => binary(builder, BinaryOp::Div, a, b),
+			Mod(a, b) => binary(builder, BinaryOp::Rem, a, b),
+
+			Assign(variable, expr) => {
+				let src = expr.lower(builder);
+				let dest = variable.lower_variable(builder);
+				builder.emit(Inst::Copy(dest, src));
+				Operand::Temp(dest)
+			}
+
+			AddAssign(variable, expr) => assign(builder, BinaryOp::Add, variable, expr),
+			SubAssign(variable, expr) => assign(builder, BinaryOp::Sub, variable, expr),
+			MulAssign(variable, expr) => assign(builder, BinaryOp::Mul, variable, expr),
+			DivAssign(variable, expr) => assign(builder, BinaryOp::Div, variable, expr),
+			ModAssign(variable, expr) => assign(builder, BinaryOp::Rem, variable, expr),
+
+			LessThan(_, _)
+			| LessOrEqual(_, _)
+			| GreaterThan(_, _)
+			| GreaterOrEqual(_, _)
+			| Equal(_, _)
+			| NotEqual(_, _) => {
+				let (comparison, a, b) = self.lower_comparison(builder).unwrap();
+				let flag = builder.temp(Width::Byte);
+				builder.emit(Inst::Binary(flag, BinaryOp::Compare(comparison), a, b));
+				let dest = builder.temp(Width::Word);
+				builder.emit(Inst::Unary(dest, UnaryOp::Extend, Operand::Temp(flag)));
+				Operand::Temp(dest)
+			}
+		}
+	}
+
+	//Whether evaluating this changes any variable.
+	fn assigns(&self) -> bool {
+		match &self.node {
+			Assign(_, _)
+			| AddAssign(_, _)
+			| SubAssign(_, _)
+			| MulAssign(_, _)
+			| DivAssign(_, _)
+			| ModAssign(_, _) => true,
+			Neg(expr) => expr.assigns(),
+			Add(a, b)
+			| Sub(a, b)
+			| Mult(a, b)
+			| Div(a, b)
+			| Mod(a, b)
+			| LessThan(a, b)
+			| LessOrEqual(a, b)
+			| GreaterThan(a, b)
+			| GreaterOrEqual(a, b)
+			| Equal(a, b)
+			| NotEqual(a, b) => a.assigns() || b.assigns(),
+			FuncCall(_, arguments) => arguments.iter().any(|arg| arg.assigns()),
+			Var(_) | Integer(_) => false,
+		}
+	}
+
+	//Semantic analysis guarantees that only variables are assigned to.
+	fn lower_variable(&self, builder: &mut Builder) -> Temp {
+		match &self.node {
+			Var(name) => builder.get_variable(name),
+			_ => panic!("COMPILER BUG: Assignment to something other than a variable"),
+		}
+	}
+
+	//If this is a comparison, lower both sides.
+	fn lower_comparison(&self, builder: &mut Builder) -> Option<(Comparison, Operand, Operand)> {
+		let (comparison, a, b) = match &self.node {
+			LessThan(a, b) => (Comparison::Less, a, b),
+			LessOrEqual(a, b) => (Comparison::LessOrEqual, a, b),
+			GreaterThan(a, b) => (Comparison::Greater, a, b),
+			GreaterOrEqual(a, b) => (Comparison::GreaterOrEqual, a, b),
+			Equal(a, b) => (Comparison::Equal, a, b),
+			NotEqual(a, b) => (Comparison::NotEqual, a, b),
+			_ => return None,
+		};
+
+		let operands = lower_all(builder, &[a, b]);
+		Some((comparison, operands[0], operands[1]))
+	}
+
+	/// End the current block by going to one block or another, depending on this expression's truth value.
+	/// Comparisons branch directly instead of producing a 0 or 1 first.
+	pub fn lower_branch(&self, builder: &mut Builder, when_true: BlockId, when_false: BlockId) {
+		let (comparison, a, b) = match self.lower_comparison(builder) {
+			Some(comparison) => comparison,
+			None => {
+				let value = self.lower(builder);
+				(Comparison::NotEqual, value, Operand::Const(0))
+			}
+		};
+		builder.terminate(Terminator::Branch(comparison, a, b, when_true, when_false));
+	}
+}
+
+//Lower expressions from left to right. A variable is used where it is, unless something after
+//it assigns to a variable, which could change it before the value is used; then it's copied.
+fn lower_all(builder: &mut Builder, exprs: &[&Expression]) -> Vec<Operand> {
+	let mut operands = vec![];
+	for (index, expr) in exprs.iter().enumerate() {
+		let mut operand = expr.lower(builder);
+		if let Operand::Temp(temp) = operand {
+			if builder.is_variable(temp) && exprs[index + 1..].iter().any(|expr| expr.assigns()) {
+				let copy = builder.temp(Width::Word);
+				builder.emit(Inst::Copy(copy, operand));
+				operand = Operand::Temp(copy);
+			}
+		}
+		operands.push(operand);
+	}
+	operands
+}
+
+//dest = a op b
+fn binary(builder: &mut Builder, op: BinaryOp, a: &Expression, b: &Expression) -> Operand {
+	let operands = lower_all(builder, &[a, b]);
+	let dest = builder.temp(Width::Word);
+	builder.emit(Inst::Binary(dest, op, operands[0], operands[1]));
+	Operand::Temp(dest)
+}
+
+//variable = variable op expr
+fn assign(
+	builder: &mut Builder,
+	op: BinaryOp,
+	variable: &Expression,
+	expr: &Expression,
+) -> Operand {
+	let src = expr.lower(builder);
+	let dest = variable.lower_variable(builder);
+	builder.emit(Inst::Binary(dest, op, Operand::Temp(dest), src));
+	Operand::Temp(dest)
+}
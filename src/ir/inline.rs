@@ -0,0 +1,182 @@
+use super::*;
+use crate::analysis::callgraph::CallGraph;
+use crate::flags::Goal;
+
+//Sizes here are counted in IR instructions (including terminators), which is rough but good
+//enough to compare a function body against the code needed to call it.
+
+//Copying each argument, the call itself, and copying the result out.
+fn call_cost(args: usize) -> usize {
+	args + 2
+}
+
+//When going for speed, bodies up to this size get copied into every caller.
+const SPEED_LIMIT: usize = 24;
+
+/// Replace calls with a copy of the called function's body, where that's worth it.
+/// Functions that have been inlined everywhere are left without callers, to be removed later.
+pub fn inline(module: &mut Module, goal: Goal) {
+	let graph = CallGraph::build(module);
+	let recursive = graph.recursive();
+
+	//Callees come before their callers, so by the time a function is copied anywhere, whatever
+	//it calls has already been inlined into it.
+	for component in graph.components() {
+		let name = &component[0];
+		if recursive.contains(name) || *name == module.entry {
+			continue;
+		}
+
+		let callee = match module.function(name) {
+			Some(callee) => callee.clone(),
+			None => continue,
+		};
+		if !worth_inlining(module, &callee, goal) {
+			continue;
+		}
+
+		for function in &mut module.functions {
+			while let Some((block, position)) = find_call(function, &callee.name) {
+				inline_call(function, block, position, &callee);
+			}
+		}
+	}
+}
+
+fn worth_inlining(module: &Module, callee: &Function, goal: Goal) -> bool {
+	let sites: Vec<usize> = module
+		.functions
+		.iter()
+		.flat_map(|function| function.blocks.iter())
+		.flat_map(|block| block.insts.iter())
+		.filter_map(|inst| match inst {
+			Inst::Call(_, name, args) if *name == callee.name => Some(args.len()),
+			_ => None,
+		})
+		.collect();
+
+	match callee.inline {
+		Inline::Always => return true,
+		Inline::Never => return false,
+		Inline::Auto => {}
+	}
+	if sites.is_empty() {
+		return false;
+	}
+
+	let body = size(callee);
+	let calls: usize = sites.iter().map(|args| call_cost(*args)).sum();
+
+	//With one caller, the body just moves there and the call goes away.
+	//Otherwise, see whether every copy of the body costs less than the calls and the original.
+	let smaller = sites.len() * body <= body + calls;
+	match goal {
+		Goal::Size => smaller,
+		Goal::Speed => smaller || body <= SPEED_LIMIT,
+	}
+}
+
+fn size(function: &Function) -> usize {
+	function
+		.blocks
+		.iter()
+		.map(|block| block.insts.len() + 1)
+		.sum()
+}
+
+fn find_call(function: &Function, name: &str) -> Option<(usize, usize)> {
+	for (index, block) in function.blocks.iter().enumerate() {
+		for (position, inst) in block.insts.iter().enumerate() {
+			if let Inst::Call(_, callee, _) = inst {
+				if callee == name {
+					return Some((index, position));
+				}
+			}
+		}
+	}
+	None
+}
+
+//Split the block at the call, and put the callee's blocks in between the two halves.
+//Parameters are copied in at the top, and every return copies out its value and jumps to the
+//second half.
+fn inline_call(function: &mut Function, block: usize, position: usize, callee: &Function) {
+	let temps = function.temps.len();
+	function.temps.extend(callee.temps.iter().map(|temp| {
+		TempInfo {
+			width: temp.width,
+			name: temp
+				.name
+				.as_ref()
+				.map(|name| format!("{}.{}", callee.name, name)),
+		}
+	}));
+	let rename = |operand: &mut Operand| {
+		if let Operand::Temp(temp) = operand {
+			temp.0 += temps;
+		}
+	};
+
+	//Make room for the callee's blocks and the second half, straight after the call.
+	let first = block + 1;
+	let rest = first + callee.blocks.len();
+	for other in &mut function.blocks {
+		for target in other.terminator.successors_mut() {
+			if target.0 > block {
+				target.0 += callee.blocks.len() + 1;
+			}
+		}
+	}
+
+	let insts = function.blocks[block].insts.split_off(position + 1);
+	let (dest, args) = match function.blocks[block].insts.pop() {
+		Some(Inst::Call(dest, _, args)) => (dest, args),
+		_ => panic!("COMPILER BUG: Inlining something that isn't a call"),
+	};
+	let terminator = std::mem::replace(
+		&mut function.blocks[block].terminator,
+		Terminator::Jump(BlockId(first)),
+	);
+	for (param, arg) in callee.params.iter().zip(args) {
+		function.blocks[block]
+			.insts
+			.push(Inst::Copy(Temp(param.0 + temps), arg));
+	}
+
+	let mut blocks = vec![];
+	for original in &callee.blocks {
+		let mut copy = original.clone();
+		for inst in &mut copy.insts {
+			match inst {
+				Inst::Copy(dest, _) | Inst::Unary(dest, _, _) | Inst::Binary(dest, _, _, _) => {
+					dest.0 += temps;
+				}
+				Inst::Call(dest, _, _) => {
+					if let Some(dest) = dest {
+						dest.0 += temps;
+					}
+				}
+			}
+			for operand in inst.operands_mut() {
+				rename(operand);
+			}
+		}
+		for operand in copy.terminator.operands_mut() {
+			rename(operand);
+		}
+		for target in copy.terminator.successors_mut() {
+			target.0 += first;
+		}
+
+		if let Terminator::Return(value) = copy.terminator {
+			if let (Some(dest), Some(value)) = (dest, value) {
+				copy.insts.push(Inst::Copy(dest, value));
+			}
+			copy.terminator = Terminator::Jump(BlockId(rest));
+		}
+		blocks.push(copy);
+	}
+	blocks.push(Block { insts, terminator });
+
+	function.blocks.splice(first..first, blocks);
+}
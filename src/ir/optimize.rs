@@ -0,0 +1,229 @@
+use super::*;
+use crate::analysis::callgraph::CallGraph;
+use crate::analysis::cfg::Cfg;
+use crate::analysis::dataflow::liveness::{self, Liveness};
+use crate::analysis::dataflow::reaching::{ReachingDefinitions, Site};
+use crate::analysis::dataflow::{self, BitSet};
+use crate::flags::Options;
+
+/// Simplify the IR without changing what the program does.
+pub fn optimize(module: &mut Module, options: &Options) {
+	remove_unused_functions(module);
+	for function in &mut module.functions {
+		remove_tail_recursion(function);
+	}
+	inline::inline(module, options.optimize_for);
+	remove_unused_functions(module);
+
+	for function in &mut module.functions {
+		//Each of these can give the other more to do.
+		loop {
+			let folded = fold_constants(function);
+			let propagated = propagate_constants(function);
+			if !folded && !propagated {
+				break;
+			}
+		}
+		strength::reduce_strength(function, options.opt_level);
+		fold_branches(function);
+		remove_unreachable_blocks(function);
+		remove_dead_code(function);
+	}
+}
+
+//Functions that nothing can ever call don't need any code.
+fn remove_unused_functions(module: &mut Module) {
+	let reachable = CallGraph::build(module).reachable(&[&module.entry]);
+	module
+		.functions
+		.retain(|function| reachable.contains(&function.name));
+}
+
+//A function that returns what a call to itself returns can start over with the new arguments
+//instead, which turns the recursion into a loop and uses no stack.
+fn remove_tail_recursion(function: &mut Function) {
+	for index in 0..function.blocks.len() {
+		let block = &function.blocks[index];
+		let args = match (block.insts.last(), &block.terminator) {
+			(
+				Some(Inst::Call(Some(dest), name, args)),
+				Terminator::Return(Some(Operand::Temp(value))),
+			) if *name == function.name && dest == value => args.clone(),
+			(Some(Inst::Call(_, name, args)), Terminator::Return(None))
+				if *name == function.name && !function.returns_value =>
+			{
+				args.clone()
+			}
+			_ => continue,
+		};
+
+		//Parameters are overwritten in order, so an argument that's an earlier parameter needs
+		//to be saved first.
+		let mut args = args;
+		let mut saves = vec![];
+		for (position, arg) in args.iter_mut().enumerate() {
+			if let Operand::Temp(temp) = *arg {
+				if function.params[..position].contains(&temp) {
+					function.temps.push(TempInfo {
+						width: Width::Word,
+						name: None,
+					});
+					let save = Temp(function.temps.len() - 1);
+					saves.push(Inst::Copy(save, *arg));
+					*arg = Operand::Temp(save);
+				}
+			}
+		}
+
+		let block = &mut function.blocks[index];
+		block.insts.pop();
+		block.insts.extend(saves);
+		for (param, arg) in function.params.iter().zip(args) {
+			block.insts.push(Inst::Copy(*param, arg));
+		}
+		block.terminator = Terminator::Jump(BlockId(0));
+	}
+}
+
+//Work out any operations whose operands are all constants. Returns whether anything changed.
+fn fold_constants(function: &mut Function) -> bool {
+	let mut changed = false;
+	for block in &mut function.blocks {
+		for inst in &mut block.insts {
+			let (dest, value) = match *inst {
+				Inst::Unary(dest, op, Operand::Const(value)) => (dest, op.apply(value)),
+				Inst::Binary(dest, op, Operand::Const(a), Operand::Const(b)) => {
+					(dest, op.apply(a, b))
+				}
+				_ => continue,
+			};
+			*inst = Inst::Copy(dest, Operand::Const(value));
+			changed = true;
+		}
+	}
+	changed
+}
+
+//Replace a use of a temporary with a constant, when every definition that can reach that use
+//sets it to the same constant. Returns whether anything changed.
+fn propagate_constants(function: &mut Function) -> bool {
+	let cfg = Cfg::build(function);
+	let reaching = ReachingDefinitions::new(function);
+	let results = dataflow::solve(&reaching, function, &cfg);
+
+	let values: Vec<Option<i16>> = reaching
+		.definitions
+		.iter()
+		.map(|definition| match definition {
+			Site::Inst(block, position) => match function.blocks[block.0].insts[*position] {
+				Inst::Copy(_, Operand::Const(value)) => Some(value),
+				_ => None,
+			},
+			Site::Param => None,
+		})
+		.collect();
+
+	let replace = |operand: &mut Operand, fact: &BitSet| -> bool {
+		if let Operand::Temp(temp) = *operand {
+			let mut definitions = reaching.of(temp, fact).map(|definition| values[definition]);
+			if let Some(Some(value)) = definitions.next() {
+				if definitions.all(|other| other == Some(value)) {
+					*operand = Operand::Const(value);
+					return true;
+				}
+			}
+		}
+		false
+	};
+
+	let mut changed = false;
+	for (index, block) in function.blocks.iter_mut().enumerate() {
+		let mut fact = results.entry[index].clone();
+		for (position, inst) in block.insts.iter_mut().enumerate() {
+			for operand in inst.operands_mut() {
+				changed |= replace(operand, &fact);
+			}
+			reaching.step(BlockId(index), position, inst, &mut fact);
+		}
+		for operand in block.terminator.operands_mut() {
+			changed |= replace(operand, &fact);
+		}
+	}
+	changed
+}
+
+//A branch on two constants always goes the same way.
+fn fold_branches(function: &mut Function) {
+	for block in &mut function.blocks {
+		if let Terminator::Branch(comparison, Operand::Const(a), Operand::Const(b), yes, no) =
+			block.terminator
+		{
+			let target = if comparison.holds(a, b) { yes } else { no };
+			block.terminator = Terminator::Jump(target);
+		}
+	}
+}
+
+//Remove blocks that can't be reached from the entry, and renumber the rest in the same order.
+fn remove_unreachable_blocks(function: &mut Function) {
+	let reachable = Cfg::build(function).reachable();
+	let mut renumbered = vec![None; reachable.len()];
+	let mut count = 0;
+	for (index, reachable) in reachable.iter().enumerate() {
+		if *reachable {
+			renumbered[index] = Some(BlockId(count));
+			count += 1;
+		}
+	}
+
+	let renumber = |block: &mut BlockId| match renumbered[block.0] {
+		Some(new) => *block = new,
+		None => panic!("COMPILER BUG: Reachable block jumps to an unreachable one"),
+	};
+
+	let blocks = std::mem::take(&mut function.blocks);
+	for (index, mut block) in blocks.into_iter().enumerate() {
+		if !reachable[index] {
+			continue;
+		}
+		for target in block.terminator.successors_mut() {
+			renumber(target);
+		}
+		function.blocks.push(block);
+	}
+}
+
+//Remove instructions whose results are never used. Calls stay, but don't keep their results.
+fn remove_dead_code(function: &mut Function) {
+	loop {
+		let cfg = Cfg::build(function);
+		let results = dataflow::solve(&Liveness, function, &cfg);
+
+		let mut changed = false;
+		for (index, block) in function.blocks.iter_mut().enumerate() {
+			let mut live = results.exit[index].clone();
+			liveness::read(block.terminator.operands(), &mut live);
+
+			let mut insts = vec![];
+			for mut inst in block.insts.drain(..).rev() {
+				if let Some(dest) = inst.dest() {
+					if !live.contains(dest.0) {
+						changed = true;
+						match &mut inst {
+							Inst::Call(dest, _, _) => *dest = None,
+							_ => continue,
+						}
+					}
+				}
+				liveness::step(&inst, &mut live);
+				insts.push(inst);
+			}
+			insts.reverse();
+			block.insts = insts;
+		}
+
+		if !changed {
+			break;
+		}
+	}
+}
@@ -0,0 +1,103 @@
+use super::builder::Builder;
+use super::*;
+use crate::parser::ast::Stmt::*;
+use crate::parser::ast::{Program, Statement};
+
+impl Program {
+	pub fn lower(&self, builder: &mut Builder) {
+		for stmt in &self.stmts {
+			stmt.lower(builder);
+
+			//Anything after this can't run, so there's no point generating code for it.
+			if stmt.always_leaves() {
+				break;
+			}
+		}
+	}
+}
+
+impl Statement {
+	pub fn lower(&self, builder: &mut Builder) {
+		match &self.node {
+			ExprStmt(expr) => {
+				expr.lower(builder);
+			}
+
+			VarDecl(_qualifiers, name, _datatype, value) => {
+				let dest = builder.variable(&name.value);
+
+				let src = value.lower(builder);
+				builder.emit(Inst::Copy(dest, src));
+
+				//Declare the variable only after evaluating its value, so it can't refer to itself.
+				builder.set_variable(&name.value, dest);
+			}
+
+			IfStmt(condition, stmts_true, stmts_false) => {
+				let true_block = builder.new_block();
+				let false_block = builder.new_block();
+				let end_block = if !stmts_false.stmts.is_empty() {
+					builder.new_block()
+				} else {
+					false_block
+				};
+
+				condition.lower_branch(builder, true_block, false_block);
+
+				builder.switch_to(true_block);
+				builder.push_scope();
+				stmts_true.lower(builder);
+				builder.pop_scope();
+				builder.terminate(Terminator::Jump(end_block));
+
+				if !stmts_false.stmts.is_empty() {
+					builder.switch_to(false_block);
+					builder.push_scope();
+					stmts_false.lower(builder);
+					builder.pop_scope();
+					builder.terminate(Terminator::Jump(end_block));
+				}
+
+				builder.switch_to(end_block);
+			}
+
+			WhileStmt(condition, stmts) => {
+				let end_block = builder.new_block();
+				let (head_block, body_block) = builder.enter_loop(end_block);
+
+				builder.terminate(Terminator::Jump(head_block));
+				builder.switch_to(head_block);
+				condition.lower_branch(builder, body_block, end_block);
+
+				builder.switch_to(body_block);
+				builder.push_scope();
+				stmts.lower(builder);
+				builder.pop_scope();
+				builder.terminate(Terminator::Jump(head_block));
+				builder.exit_loop();
+
+				builder.switch_to(end_block);
+			}
+
+			BreakStmt => {
+				let (_, end_block) = builder.current_loop();
+				builder.terminate(Terminator::Jump(end_block));
+			}
+
+			ContinueStmt => {
+				let (head_block, _) = builder.current_loop();
+				builder.terminate(Terminator::Jump(head_block));
+			}
+
+			ReturnStmt(expr) => {
+				let value = expr.as_ref().as_ref().map(|expr| expr.lower(builder));
+				builder.terminate(Terminator::Return(value));
+			}
+
+			FuncDecl(_, _, _, _, _, _) => {
+				//Semantic analysis guarantees that functions aren't nested.
+				panic!("COMPILER BUG: Function declared inside another function");
+			}
+		}
+	}
+}
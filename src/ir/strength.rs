@@ -0,0 +1,160 @@
+use super::*;
+
+//The 6502 can't multiply or divide, so `*`, `/` and `%` call runtime routines that take hundreds
+//of cycles. By a constant, most of that work can be done at compile time instead.
+
+//At `-O2`, multiplies by constants with up to this many bits set become shifts and adds.
+const MAX_ADDS: u32 = 3;
+
+/// Replace multiplies, divides and remainders by constants with shifts, masks and adds.
+/// At level 1 only powers of two are handled, which makes the code both smaller and faster.
+/// Level 2 also handles other small multipliers, which is faster but can take more space.
+pub fn reduce_strength(function: &mut Function, level: u8) {
+	for index in 0..function.blocks.len() {
+		let insts = std::mem::take(&mut function.blocks[index].insts);
+		let mut result = Vec::with_capacity(insts.len());
+
+		for inst in insts {
+			let mut reducer = Reducer {
+				function,
+				insts: &mut result,
+			};
+			let reduced = match inst {
+				Inst::Binary(dest, BinaryOp::Mul, a, Operand::Const(b))
+				| Inst::Binary(dest, BinaryOp::Mul, Operand::Const(b), a) => reducer.mul(dest, a, b, level),
+				Inst::Binary(dest, BinaryOp::Div, a, Operand::Const(b)) => reducer.div(dest, a, b),
+				Inst::Binary(dest, BinaryOp::Rem, a, Operand::Const(b)) => reducer.rem(dest, a, b),
+				_ => false,
+			};
+			if !reduced {
+				result.push(inst);
+			}
+		}
+
+		function.blocks[index].insts = result;
+	}
+}
+
+//Emits the replacement for a single instruction.
+struct Reducer<'a> {
+	function: &'a mut Function,
+	insts: &'a mut Vec<Inst>,
+}
+
+impl Reducer<'_> {
+	fn temp(&mut self) -> Temp {
+		self.function.temps.push(TempInfo {
+			width: Width::Word,
+			name: None,
+		});
+		Temp(self.function.temps.len() - 1)
+	}
+
+	fn binary(&mut self, op: BinaryOp, a: Operand, b: Operand) -> Operand {
+		let dest = self.temp();
+		self.insts.push(Inst::Binary(dest, op, a, b));
+		Operand::Temp(dest)
+	}
+
+	//The result of a sequence goes in a temporary, then gets moved (and maybe negated) into place.
+	fn finish(&mut self, dest: Temp, value: Operand, negate: bool) -> bool {
+		if negate {
+			self.insts.push(Inst::Unary(dest, UnaryOp::Neg, value));
+		} else {
+			self.insts.push(Inst::Copy(dest, value));
+		}
+		true
+	}
+
+	fn mul(&mut self, dest: Temp, a: Operand, b: i16, level: u8) -> bool {
+		//Multiplying wraps around, so -32768 works like 32768 and is a power of two too.
+		if (b as u16).is_power_of_two() {
+			let shifted = self.shift(BinaryOp::ShiftLeft, a, (b as u16).trailing_zeros());
+			return self.finish(dest, shifted, false);
+		}
+
+		match b {
+			0 => return self.finish(dest, Operand::Const(0), false),
+			-1 => return self.finish(dest, a, true),
+			_ => {}
+		}
+
+		//Without its sign, the multiplier is at most 32767 here.
+		let magnitude = b.unsigned_abs();
+		if magnitude.is_power_of_two() {
+			let shifted = self.shift(BinaryOp::ShiftLeft, a, magnitude.trailing_zeros());
+			return self.finish(dest, shifted, b < 0);
+		}
+		if level < 2 || magnitude.count_ones() > MAX_ADDS {
+			return false;
+		}
+
+		//Add up a shifted copy of `a` for each bit that's set, shifting further each time.
+		let mut shifted = a;
+		let mut position = 0;
+		let mut sum = None;
+		for bit in 0..16 {
+			if magnitude & (1 << bit) == 0 {
+				continue;
+			}
+			shifted = self.shift(BinaryOp::ShiftLeft, shifted, bit - position);
+			position = bit;
+			sum = Some(match sum {
+				None => shifted,
+				Some(sum) => self.binary(BinaryOp::Add, sum, shifted),
+			});
+		}
+		self.finish(dest, sum.unwrap(), b < 0)
+	}
+
+	fn div(&mut self, dest: Temp, a: Operand, b: i16) -> bool {
+		match b {
+			1 => return self.finish(dest, a, false),
+			-1 => return self.finish(dest, a, true),
+			_ => {}
+		}
+		let magnitude = b.unsigned_abs();
+		if b == i16::MIN || !magnitude.is_power_of_two() {
+			return false;
+		}
+
+		//Shifting right rounds down, but division rounds toward zero. So negative numbers need
+		//2^k - 1 adding first, which rounds them up instead. `a / -b` is just `-(a / b)`.
+		let bits = magnitude.trailing_zeros();
+		let biased = self.bias(a, bits);
+		let quotient = self.shift(BinaryOp::ShiftRight, biased, bits);
+		self.finish(dest, quotient, b < 0)
+	}
+
+	fn rem(&mut self, dest: Temp, a: Operand, b: i16) -> bool {
+		if b == 1 || b == -1 {
+			return self.finish(dest, Operand::Const(0), false);
+		}
+		let magnitude = b.unsigned_abs();
+		if b == i16::MIN || !magnitude.is_power_of_two() {
+			return false;
+		}
+
+		//The remainder is what's left after taking off the quotient times the divisor, which
+		//is just the biased value with its low bits cleared. The sign of the divisor doesn't matter.
+		let bits = magnitude.trailing_zeros();
+		let biased = self.bias(a, bits);
+		let multiple = self.binary(BinaryOp::And, biased, Operand::Const(-(magnitude as i16)));
+		let remainder = self.binary(BinaryOp::Sub, a, multiple);
+		self.finish(dest, remainder, false)
+	}
+
+	//a + (2^bits - 1) if a is negative, otherwise just a.
+	fn bias(&mut self, a: Operand, bits: u32) -> Operand {
+		let sign = self.binary(BinaryOp::ShiftRight, a, Operand::Const(15));
+		let bias = self.binary(BinaryOp::And, sign, Operand::Const((1 << bits) - 1));
+		self.binary(BinaryOp::Add, a, bias)
+	}
+
+	fn shift(&mut self, op: BinaryOp, a: Operand, bits: u32) -> Operand {
+		if bits == 0 {
+			return a;
+		}
+		self.binary(op, a, Operand::Const(bits as i16))
+	}
+}
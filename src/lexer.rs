@@ -43,6 +43,7 @@ pub enum Token {
 	Arrow,
 	Semicolon,
 	Dot,
+	At,
 
 	//Operators
 	OperPlus,
@@ -107,6 +108,7 @@ lexer! {
 	"->" => Token::Arrow,
 	";" => Token::Semicolon,
 	"\\." => Token::Dot,
+	"@" => Token::At,
 
 	//Operators
 	"\\+" => Token::OperPlus,
@@ -142,7 +144,7 @@ impl<'a> Lexer<'a> {
 		Lexer {
 			original: context.source,
 			remaining: context.source,
-			context: context,
+			context,
 		}
 	}
 }
@@ -175,7 +177,7 @@ impl<'a> Iterator for Lexer<'a> {
 					message::error(
 						format!("unexpected character `{}`", text),
 						Some(span),
-						Some(&self.context),
+						Some(self.context),
 					);
 					continue;
 				}
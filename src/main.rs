@@ -1,11 +1,23 @@
 use std::fs;
 use std::io;
+use std::io::Write;
 use std::process::ExitCode;
 
+mod analysis;
 mod codegen;
+mod emulator;
+mod ir;
 mod lexer;
+//plex generates the parser from the grammar, with a closure for each rule and a `&mut Vec` stack.
+#[allow(
+	clippy::redundant_closure_call,
+	clippy::ptr_arg,
+	clippy::type_complexity
+)]
 mod parser;
+mod runner;
 mod semantics;
+mod target;
 use codegen::asm::Bytecode;
 
 mod flags;
@@ -23,22 +35,53 @@ fn main() -> ExitCode {
 		*message::LANGUAGE_SERVER.lock().unwrap() = true;
 	}
 
+	let target = match target::Target::find(&options.target) {
+		Ok(target) => target,
+		Err(error) => {
+			message::error(error, None, None);
+			message::abort();
+			return ExitCode::FAILURE;
+		}
+	};
+	if options.test && options.target != "emulator" {
+		message::error(
+			format!(
+				"Tests can only be run on the `emulator` target, not `{}`",
+				target.name
+			),
+			None,
+			None,
+		);
+		message::abort();
+		return ExitCode::FAILURE;
+	}
+	if options.run && target.hardware.is_none() {
+		message::error(
+			format!("Target `{}` can't be run on the emulator", target.name),
+			None,
+			None,
+		);
+		message::abort();
+		return ExitCode::FAILURE;
+	}
+
 	//Read input file
+	let input = options.input.as_ref().unwrap();
 	let mut s = String::new();
-	let filename = if options.input.to_str().unwrap() == "-" {
+	let filename = if input.to_str().unwrap() == "-" {
 		for line in io::stdin().lines() {
 			s += &line.unwrap();
 		}
 		"stdin"
 	} else {
-		s = match fs::read_to_string(&options.input) {
+		s = match fs::read_to_string(input) {
 			Ok(file_contents) => file_contents,
 			Err(error) => {
-				eprintln!("Error reading file {:?}: {}", options.input, error);
+				eprintln!("Error reading file {:?}: {}", input, error);
 				return ExitCode::FAILURE;
 			}
 		};
-		options.input.to_str().unwrap()
+		input.to_str().unwrap()
 	}
 	.to_string();
 
@@ -60,14 +103,10 @@ fn main() -> ExitCode {
 			match e.0 {
 				None => {
 					//We hit EOF
-					message::error(
-						format!("{}", "Unexpected end of file"),
-						None,
-						Some(&context),
-					);
+					message::error("Unexpected end of file".to_string(), None, Some(&context));
 				}
 				Some(s) => {
-					message::error(format!("{}", e.1), Some(s.1), Some(&context));
+					message::error(e.1.to_string(), Some(s.1), Some(&context));
 				}
 			};
 
@@ -94,7 +133,20 @@ fn main() -> ExitCode {
 	}
 
 	message::info("Running semantic analysis...");
-	let _analysis = semantics::Analyzer::run(&ast, &context, &options);
+	let analyzer = semantics::Analyzer::run(&ast, &context, &options);
+
+	if message::errored() {
+		message::abort();
+		return if options.language_server {
+			ExitCode::SUCCESS
+		} else {
+			ExitCode::FAILURE
+		};
+	}
+
+	//Some checks need to know how control flows through each function, so they're done on the IR.
+	let mut module = ir::lower(&ast);
+	analyzer.check_flow(&module);
 
 	if message::errored() {
 		message::abort();
@@ -111,33 +163,62 @@ fn main() -> ExitCode {
 		return ExitCode::SUCCESS;
 	}
 
-	//Program is OK, generate code.
-	let bytecode = ast.codegen();
+	//Every test is built into a program of its own.
+	if options.test {
+		return runner::test(&module, &context, &target, &options);
+	}
 
-	//Here is where we'd run bytecode optimizations if we had them.
+	//Program is OK, optimize and generate code.
+	module.start_at("main");
+	if options.opt_level > 0 {
+		ir::optimize(&mut module, &options);
+	}
+
+	//--ir flag is only available in debug builds
+	#[cfg(debug_assertions)]
+	if options.ir {
+		println!("{}", module);
+	}
+
+	let bytecode = codegen::Generator::run(&module, &context, &target, &options);
+	if message::errored() {
+		message::abort();
+		return ExitCode::FAILURE;
+	}
+
+	let bytecode = codegen::peephole::optimize(bytecode, &options);
 
-	//Convert code to text
-	let output_text = if options.binary {
-		Bytecode::output_binary(bytecode)
+	if options.run {
+		return runner::run(bytecode, &context, &target);
+	}
+
+	//Convert code to text, or assemble it into machine code
+	let output = if options.binary {
+		let machine_code = target.assemble(bytecode);
+		if message::errored() {
+			message::abort();
+			return ExitCode::FAILURE;
+		}
+		machine_code
 	} else {
-		Bytecode::output_text(bytecode)
+		Bytecode::output_text(bytecode).into_bytes()
 	};
 
 	//Output to file or stdout
-	let filename = options.output.to_str().unwrap();
+	let filename = options.output.unwrap();
+	let filename = filename.to_str().unwrap();
 	if filename == "-" {
-		print!("{}", output_text);
+		if let Err(error) = io::stdout().write_all(&output) {
+			eprintln!("{}", error);
+			return ExitCode::FAILURE;
+		}
 	} else {
-		match fs::write(filename, output_text) {
-			Err(error) => {
-				eprintln!("{}", error);
-				return ExitCode::FAILURE;
-			}
-
-			Ok(_) => {}
+		if let Err(error) = fs::write(filename, output) {
+			eprintln!("{}", error);
+			return ExitCode::FAILURE;
 		}
 	}
 
 	message::info("Finished compilation.");
-	return ExitCode::SUCCESS;
+	ExitCode::SUCCESS
 }
@@ -43,7 +43,7 @@ fn print_message(text: String, span: Option<Span>, context: Option<&Context>) {
 							&lines[lines.len() - 2]
 						);
 					}
-					if lines.len() > 0 {
+					if !lines.is_empty() {
 						eprintln!(
 							"{:<3}{} {} {}",
 							format!("{}", lines.len()).bright_blue().bold(),
@@ -226,7 +226,7 @@ pub fn context(span: Span, context: &Context) {
 	print_context(Some(context.filename), context.source, span);
 }
 
-fn print_context(filename: Option<&String>, full_text: &String, span: Span) {
+fn print_context(filename: Option<&String>, full_text: &str, span: Span) {
 	let before = &full_text[0..span.lo];
 	let after = &full_text[span.hi..full_text.len()];
 	let line_begin = (1 + {
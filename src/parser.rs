@@ -12,6 +12,11 @@ pub mod ast {
 		pub node: Stmt,
 	}
 
+	#[allow(
+		clippy::box_collection,
+		clippy::enum_variant_names,
+		clippy::tabs_in_doc_comments
+	)]
 	#[derive(Debug)]
 	pub enum Stmt {
 		/** param1: expression */
@@ -22,9 +27,18 @@ pub mod ast {
 		param2: parameters
 		param3: return type
 		param4: function body
+		param5: attributes, e.g. `inline` for `@inline`
+		param6: the word before `funk`, if any, e.g. `test` for `test funk`
 		```
 		*/
-		FuncDecl(Box<Ident>, Box<Vec<Param>>, Box<Ident>, Box<Program>),
+		FuncDecl(
+			Box<Ident>,
+			Box<Vec<Param>>,
+			Box<Ident>,
+			Box<Program>,
+			Box<Vec<Ident>>,
+			Box<Option<Ident>>,
+		),
 		ReturnStmt(Box<Option<Expression>>),
 		IfStmt(Box<Expression>, Box<Program>, Box<Program>),
 		VarDecl(
@@ -44,6 +58,7 @@ pub mod ast {
 		pub node: Expr,
 	}
 
+	#[allow(clippy::box_collection)]
 	#[derive(Debug)]
 	pub enum Expr {
 		//Arithmetic
@@ -129,14 +144,14 @@ parser! {
 			node: Stmt::ExprStmt(Box::new(e)),
 		},
 
-		KwdFunction ident[name] LParen RParen Arrow ident[return_type] LBrace program[p] RBrace => Statement {
+		function_prefix[f] KwdFunction ident[name] LParen RParen Arrow ident[return_type] LBrace program[p] RBrace => Statement {
 			span: span!(),
-			node: Stmt::FuncDecl(Box::new(name), Box::new(vec![]), Box::new(return_type), Box::new(p)),
+			node: Stmt::FuncDecl(Box::new(name), Box::default(), Box::new(return_type), Box::new(p), Box::new(f.0), Box::new(f.1)),
 		},
 
-		KwdFunction ident[name] LParen param_decl_list[params] RParen Arrow ident[return_type] LBrace program[p] RBrace => Statement {
+		function_prefix[f] KwdFunction ident[name] LParen param_decl_list[params] RParen Arrow ident[return_type] LBrace program[p] RBrace => Statement {
 			span: span!(),
-			node: Stmt::FuncDecl(Box::new(name), Box::new(params), Box::new(return_type), Box::new(p)),
+			node: Stmt::FuncDecl(Box::new(name), Box::new(params), Box::new(return_type), Box::new(p), Box::new(f.0), Box::new(f.1)),
 		},
 
 		KwdReturn assign[e] Semicolon => Statement {
@@ -190,7 +205,32 @@ parser! {
 	ident: Ident {
 		Identifier(value) => Ident {
 			span: span!(),
-			value: value,
+			value,
+		}
+	}
+
+	//Attributes go before a function declaration, e.g. `@inline funk ...`, followed by a word
+	//that says what kind of function it is, e.g. `test funk ...`. The word isn't a keyword, so
+	//`test` can still name a variable or another function.
+	function_prefix: (Vec<Ident>, Option<Ident>) {
+		=> (vec![], None),
+		ident[word] => (vec![], Some(word)),
+		attributes[a] => (a, None),
+		attributes[a] ident[word] => (a, Some(word)),
+	}
+
+	attributes: Vec<Ident> {
+		attribute[name] => vec![name],
+		attributes[mut a] attribute[name] => {
+			a.push(name);
+			a
+		}
+	}
+
+	attribute: Ident {
+		At Identifier(value) => Ident {
+			span: span!(),
+			value,
 		}
 	}
 
@@ -215,8 +255,8 @@ parser! {
 	param_decl: Param {
 		ident[name] Colon ident[datatype] => Param {
 			span: span!(),
-			name: name,
-			datatype: datatype,
+			name,
+			datatype,
 		}
 	}
 
@@ -344,7 +384,7 @@ parser! {
 
 		atom[lhs] LParen RParen => Expression {
 			span: span!(),
-			node: Expr::FuncCall(Box::new(lhs), Box::new(vec![])),
+			node: Expr::FuncCall(Box::new(lhs), Box::default()),
 		},
 
 		LParen assign[a] RParen => a,
@@ -381,5 +421,5 @@ pub fn pretty(ast: &Program) -> String {
 	let s2 = spans.replace_all(&s1, " $1");
 	let s3 = other.replace_all(&s2, "$1 $3".bold().yellow().to_string());
 
-	return s3.to_string();
+	s3.to_string()
 }
@@ -0,0 +1,199 @@
+//Running compiled programs on the emulator: either the program itself (`--run`), or each of its
+//tests in turn (`--test`).
+
+use crate::codegen::{self, asm::Bytecode};
+use crate::emulator::{self, Failure, Machine};
+use crate::flags::Options;
+use crate::ir;
+use crate::message::{self, Context};
+use crate::target::Target;
+use std::io;
+use std::io::Write;
+use std::process::ExitCode;
+
+//Assemble the program and run it until it halts, on the emulator standing in for the target's
+//computer, which has to be one it can stand in for.
+//Returns None if it couldn't be assembled; the errors have already been reported.
+fn execute(bytecode: Vec<Bytecode>, target: &Target) -> Option<(Machine, Result<(), String>)> {
+	let hardware = target.hardware.as_ref().unwrap();
+	let machine_code = target.assemble(bytecode);
+	let firmware = hardware
+		.firmware
+		.as_ref()
+		.map(|(origin, code)| (*origin, Bytecode::output_binary(code.clone(), *origin)));
+	if message::errored() {
+		return None;
+	}
+
+	//The load address isn't part of what's loaded.
+	let image = if target.load_address {
+		&machine_code[2..]
+	} else {
+		&machine_code[..]
+	};
+	let mut machine = Machine::new(hardware.regions.clone());
+	let mut result = machine.load(image, target.rom.0);
+	if let Some((origin, firmware)) = firmware {
+		result = result.and_then(|_| machine.load(&firmware, origin));
+	}
+	let result = result.and_then(|_| machine.run(emulator::MAX_CYCLES));
+	Some((machine, result))
+}
+
+fn report_failure(failure: &Failure, context: &Context) {
+	match failure {
+		Failure::Assertion { span, values } => {
+			let text = match values {
+				None => "Assertion failed".to_string(),
+				Some((left, right)) => {
+					format!("Assertion failed: left is {}, right is {}", left, right)
+				}
+			};
+			message::error(text, Some(*span), Some(context));
+		}
+		Failure::OutOfStack => {
+			message::error("Program ran out of stack space".to_string(), None, None)
+		}
+	}
+}
+
+fn write_output(output: &[u8]) -> bool {
+	if let Err(error) = io::stdout().write_all(output) {
+		eprintln!("{}", error);
+		return false;
+	}
+	true
+}
+
+/// Run a compiled program, printing what it prints.
+pub fn run(bytecode: Vec<Bytecode>, context: &Context, target: &Target) -> ExitCode {
+	message::info("Running program...");
+	let (machine, result) = match execute(bytecode, target) {
+		Some(run) => run,
+		None => {
+			message::abort();
+			return ExitCode::FAILURE;
+		}
+	};
+
+	//Show whatever was printed, even if the program crashed partway through.
+	if !write_output(&machine.memory.output) {
+		return ExitCode::FAILURE;
+	}
+
+	//Other targets show failures themselves, so only the emulator leaves details to report.
+	let failure = match target.assert_details {
+		Some(_) => machine.failure(),
+		None => None,
+	};
+	let error = match (result, failure) {
+		(Err(error), _) => error,
+		(Ok(_), Some(failure)) => {
+			report_failure(&failure, context);
+			message::abort();
+			return ExitCode::FAILURE;
+		}
+		(Ok(_), None) => {
+			message::info(&format!(
+				"Program halted after {} cycles.",
+				machine.cycles()
+			));
+			return ExitCode::SUCCESS;
+		}
+	};
+
+	message::error(error, None, None);
+	message::abort();
+	ExitCode::FAILURE
+}
+
+//How a test went.
+enum Outcome {
+	Passed,
+	Failed(Failure),
+	Crashed(String),
+}
+
+/// Build each `test funk` into an image of its own, with the test as the entry point, run them all,
+/// and report which passed. Failures are reported once every test has run, along with anything
+/// the failing test printed.
+pub fn test(
+	module: &ir::Module,
+	context: &Context,
+	target: &Target,
+	options: &Options,
+) -> ExitCode {
+	let tests: Vec<&ir::Function> = module
+		.functions
+		.iter()
+		.filter(|function| function.test)
+		.collect();
+
+	message::info(&format!("Running {} tests...", tests.len()));
+	let mut failures = vec![];
+	for test in &tests {
+		let mut image = module.clone();
+		image.start_at(&test.name);
+		if options.opt_level > 0 {
+			ir::optimize(&mut image, options);
+		}
+
+		let bytecode = codegen::Generator::run(&image, context, target, options);
+		if message::errored() {
+			message::abort();
+			return ExitCode::FAILURE;
+		}
+		let bytecode = codegen::peephole::optimize(bytecode, options);
+
+		let (machine, result) = match execute(bytecode, target) {
+			Some(run) => run,
+			None => {
+				message::abort();
+				return ExitCode::FAILURE;
+			}
+		};
+
+		let outcome = match (result, machine.failure()) {
+			(Err(error), _) => Outcome::Crashed(error),
+			(Ok(_), Some(failure)) => Outcome::Failed(failure),
+			(Ok(_), None) => Outcome::Passed,
+		};
+		match outcome {
+			Outcome::Passed => println!("test {} ... ok", test.name),
+			_ => {
+				println!("test {} ... FAILED", test.name);
+				failures.push((test, outcome, machine.memory.output));
+			}
+		}
+	}
+
+	for (test, outcome, output) in &failures {
+		println!();
+		println!("---- {} ----", test.name);
+		if !write_output(output) {
+			return ExitCode::FAILURE;
+		}
+		match outcome {
+			Outcome::Failed(failure) => report_failure(failure, context),
+			Outcome::Crashed(error) => message::error(
+				format!("Test `{}` did not finish: {}", test.name, error),
+				Some(test.span),
+				Some(context),
+			),
+			Outcome::Passed => {}
+		}
+	}
+
+	println!();
+	println!(
+		"test result: {}. {} passed, {} failed",
+		if failures.is_empty() { "ok" } else { "FAILED" },
+		tests.len() - failures.len(),
+		failures.len()
+	);
+
+	if !failures.is_empty() {
+		return ExitCode::FAILURE;
+	}
+	ExitCode::SUCCESS
+}
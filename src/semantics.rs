@@ -6,12 +6,17 @@ use std::collections::HashMap;
 
 mod assign;
 mod expression;
+mod flow;
+mod fold;
 mod program;
 mod statement;
+mod unreachable;
 
 pub struct FuncSig {
 	return_type: String,
 	param_types: Vec<String>,
+	//Tests can't be called, only run by the test runner.
+	test: bool,
 }
 
 pub struct VarSig {
@@ -20,6 +25,8 @@ pub struct VarSig {
 	span: Span,
 	used: i64,
 	changed: i64,
+	//Value of an immutable variable, if it's known at compile time
+	value: Option<i16>,
 }
 
 impl std::fmt::Display for FuncSig {
@@ -62,11 +69,11 @@ impl<'a> Analyzer<'a> {
 
 	pub fn run(ast: &Program, context: &'a Context, flags: &'a Options) -> Analyzer<'a> {
 		let mut analyzer = Analyzer {
-			context: context,
+			context,
 			scopes: vec![Scope::new()],
 			func_stack: vec![],
 			loops: 0,
-			flags: flags,
+			flags,
 		};
 
 		analyzer.set_function(
@@ -74,6 +81,16 @@ impl<'a> Analyzer<'a> {
 			vec![Analyzer::INT.to_string()],
 			Analyzer::VOID,
 		);
+		analyzer.set_function(
+			&String::from("assert"),
+			vec![Analyzer::INT.to_string()],
+			Analyzer::VOID,
+		);
+		analyzer.set_function(
+			&String::from("assert_eq"),
+			vec![Analyzer::INT.to_string(), Analyzer::INT.to_string()],
+			Analyzer::VOID,
+		);
 
 		ast.analyze(&mut analyzer);
 		analyzer
@@ -115,17 +132,27 @@ impl<'a> Analyzer<'a> {
 			FuncSig {
 				return_type: return_type.to_string(),
 				param_types: params,
+				test: false,
 			},
 		);
 	}
 
+	/// Mark a function in the current scope as a test.
+	pub fn set_test(&mut self, name: &String) {
+		let scope = self.scopes.last_mut().unwrap();
+		if let Some(func) = scope.functions.get_mut(name) {
+			func.test = true;
+		}
+	}
+
 	pub fn valid_return_type(&self, return_type: &String) -> bool {
 		["int", "void"].iter().any(|&s| s == return_type)
 	}
 
 	pub fn get_variable(&self, name: &String, all_scopes: bool) -> Option<&VarSig> {
 		if all_scopes {
-			for scope in &self.scopes {
+			//Inner scopes can shadow variables from outer ones.
+			for scope in self.scopes.iter().rev() {
 				let var = scope.variables.get(name);
 				match var {
 					None => {}
@@ -146,17 +173,26 @@ impl<'a> Analyzer<'a> {
 			name.to_string(),
 			VarSig {
 				data_type: data_type.to_string(),
-				mutable: mutable,
+				mutable,
 				span: Span {
 					lo: span.lo,
 					hi: span.hi,
 				},
 				used: 0,
 				changed: 0,
+				value: None,
 			},
 		);
 	}
 
+	/// Record the value of an immutable variable in the current scope, when it's known at compile time.
+	pub fn set_constant(&mut self, name: &String, value: i16) {
+		let scope = self.scopes.last_mut().unwrap();
+		if let Some(var) = scope.variables.get_mut(name) {
+			var.value = Some(value);
+		}
+	}
+
 	pub fn change_variable(&mut self, name: &String) {
 		for scope in &mut self.scopes {
 			let var = scope.variables.get_mut(name);
@@ -25,7 +25,7 @@ impl Expression {
 		}
 
 		match &variable.node {
-			Var(id) => match analyzer.get_variable(&id, true) {
+			Var(id) => match analyzer.get_variable(id, true) {
 				None => {
 					message::error(
 						format!("Use of undeclared variable `{}`", id),
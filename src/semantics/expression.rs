@@ -52,6 +52,7 @@ impl Expression {
 								Some(analyzer.context),
 							);
 						}
+						self.check_overflow(analyzer);
 						Analyzer::INT.to_string()
 					}
 				}
@@ -61,6 +62,7 @@ impl Expression {
 				let type1 = a.analyze(analyzer);
 				let type2 = b.analyze(analyzer);
 				self.check_binary_arithmetic(analyzer, type1, type2);
+				self.check_overflow(analyzer);
 				Analyzer::INT.to_string()
 			}
 
@@ -68,6 +70,7 @@ impl Expression {
 				let type1 = a.analyze(analyzer);
 				let type2 = b.analyze(analyzer);
 				self.check_binary_arithmetic(analyzer, type1, type2);
+				self.check_overflow(analyzer);
 				Analyzer::INT.to_string()
 			}
 
@@ -75,6 +78,7 @@ impl Expression {
 				let type1 = a.analyze(analyzer);
 				let type2 = b.analyze(analyzer);
 				self.check_binary_arithmetic(analyzer, type1, type2);
+				self.check_overflow(analyzer);
 				Analyzer::INT.to_string()
 			}
 
@@ -83,18 +87,14 @@ impl Expression {
 				let type2 = b.analyze(analyzer);
 				self.check_binary_arithmetic(analyzer, type1, type2);
 
-				match b.node {
-					Integer(value) => {
-						if value == 0 {
-							message::error(
-								"Division by zero".to_string(),
-								Some(b.span),
-								Some(analyzer.context),
-							);
-						}
-					}
-					_ => {}
+				if b.constant(analyzer) == Some(0) {
+					message::error(
+						"Division by zero".to_string(),
+						Some(b.span),
+						Some(analyzer.context),
+					);
 				}
+				self.check_overflow(analyzer);
 
 				Analyzer::INT.to_string()
 			}
@@ -104,18 +104,14 @@ impl Expression {
 				let type2 = b.analyze(analyzer);
 				self.check_binary_arithmetic(analyzer, type1, type2);
 
-				match b.node {
-					Integer(value) => {
-						if value == 0 {
-							message::error(
-								"Division by zero".to_string(),
-								Some(b.span),
-								Some(analyzer.context),
-							);
-						}
-					}
-					_ => {}
+				if b.constant(analyzer) == Some(0) {
+					message::error(
+						"Division by zero".to_string(),
+						Some(b.span),
+						Some(analyzer.context),
+					);
 				}
+				self.check_overflow(analyzer);
 
 				Analyzer::INT.to_string()
 			}
@@ -178,6 +174,18 @@ impl Expression {
 							Analyzer::VOID.to_string()
 						}
 
+						Some(func) if func.test => {
+							message::error(
+								format!(
+									"Test `{}` cannot be called. Tests only run under `aglet test`",
+									id
+								),
+								Some(name.span),
+								Some(analyzer.context),
+							);
+							Analyzer::VOID.to_string()
+						}
+
 						Some(func) => {
 							let ct = func.param_types.len();
 							if params.len() != ct {
@@ -0,0 +1,83 @@
+use crate::analysis::callgraph::CallGraph;
+use crate::analysis::cfg::Cfg;
+use crate::ir::{Inline, Module, Terminator};
+use crate::lexer::Span;
+use crate::message;
+use crate::semantics::Analyzer;
+
+impl Analyzer<'_> {
+	/// Checks that depend on how control flows through each function.
+	/// These are done on the IR, which must have been lowered from a program that passed `run()`.
+	pub fn check_flow(&self, module: &Module) {
+		//Programs start at `main`, but each test is a program of its own that doesn't need it.
+		if !self.flags.test && module.function(&module.entry).is_none() {
+			//There's nowhere better to point than the start of the file, if there's anything there.
+			let start = Span { lo: 0, hi: 1 };
+			message::error(
+				format!(
+					"No `{}` function. A program starts by calling `funk {}() -> void`",
+					module.entry, module.entry
+				),
+				Some(start).filter(|_| !self.context.source.is_empty()),
+				Some(self.context),
+			);
+		}
+
+		let graph = CallGraph::build(module);
+		//Tests start running on their own, just like the program does.
+		let mut entries = vec![module.entry.as_str()];
+		for function in &module.functions {
+			if function.test {
+				entries.push(&function.name);
+			}
+		}
+		let called = graph.reachable(&entries);
+		let recursive = graph.recursive();
+
+		for function in &module.functions {
+			let cfg = Cfg::build(function);
+			let reachable = cfg.reachable();
+
+			//Falling off the end of a function is a return without a value.
+			let returns_nothing = function.blocks.iter().enumerate().any(|(index, block)| {
+				reachable[index] && block.terminator == Terminator::Return(None)
+			});
+
+			if function.returns_value && returns_nothing {
+				message::error(
+					format!(
+						"Function `{}` might not return a value. A value of type `{}` must always be returned",
+						function.name,
+						Analyzer::INT
+					),
+					Some(function.span),
+					Some(self.context),
+				);
+			}
+
+			//Each call would need another copy of the body, forever.
+			if function.inline == Inline::Always && recursive.contains(&function.name) {
+				message::error(
+					format!(
+						"Function `{}` cannot be inlined, because it can end up calling itself",
+						function.name
+					),
+					Some(function.span),
+					Some(self.context),
+				);
+			}
+
+			//Functions that only other unused functions call can't run either.
+			if !called.contains(&function.name)
+				&& !function.name.starts_with("_")
+				&& !self.flags.warn_suppress
+			{
+				message::warning(
+					format!("Function `{name}` is never called. If this is intentional, prefix the function name with an underscore (e.g. `_{name}`)", name = function.name),
+					Some(function.span),
+					Some(self.context),
+				);
+			}
+		}
+	}
+}
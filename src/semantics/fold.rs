@@ -0,0 +1,105 @@
+use crate::ir::{BinaryOp, Comparison, UnaryOp};
+use crate::message;
+use crate::parser::ast::Expr::*;
+use crate::parser::ast::Expression;
+use crate::semantics::Analyzer;
+
+impl Expression {
+	/// The value of this expression, if it can be worked out at compile time.
+	/// Arithmetic wraps around exactly like it does in the generated code.
+	pub fn constant(&self, analyzer: &Analyzer) -> Option<i16> {
+		self.evaluate(analyzer).map(|(value, _)| value)
+	}
+
+	/// Warn if this expression has a constant value that doesn't fit in 2 bytes.
+	/// Only the outermost operation is checked, since its operands get checked when they're analyzed.
+	pub fn check_overflow(&self, analyzer: &Analyzer) {
+		if let Some((value, exact)) = self.evaluate(analyzer) {
+			if value as i64 != exact && !analyzer.flags.warn_suppress {
+				message::warning(
+					format!(
+						"Arithmetic overflow: the result is {}, which doesn't fit in a signed 2-byte integer and wraps around to {}",
+						exact, value
+					),
+					Some(self.span),
+					Some(analyzer.context),
+				);
+			}
+		}
+	}
+
+	//The constant value after wrapping around, along with the exact result of the outermost operation.
+	fn evaluate(&self, analyzer: &Analyzer) -> Option<(i16, i64)> {
+		match &self.node {
+			//Out of range literals are already an error.
+			Integer(value) => match i16::try_from(*value) {
+				Ok(value) => Some((value, value as i64)),
+				Err(_) => None,
+			},
+
+			Neg(expr) => match expr.node {
+				//Handle this separately, since 32768 by itself doesn't fit in 16 bits.
+				Integer(value) => match i16::try_from(-value) {
+					Ok(value) => Some((value, value as i64)),
+					Err(_) => None,
+				},
+				_ => {
+					let value = expr.constant(analyzer)?;
+					Some((UnaryOp::Neg.apply(value), -(value as i64)))
+				}
+			},
+
+			//Only immutable variables have a value that's known everywhere they can be used.
+			Var(name) => {
+				let value = analyzer.get_variable(name, true)?.value?;
+				Some((value, value as i64))
+			}
+
+			Add(a, b) => binary(analyzer, BinaryOp::Add, a, b),
+			Sub(a, b) => binary(analyzer, BinaryOp::Sub, a, b),
+			Mult(a, b) => binary(analyzer, BinaryOp::Mul, a, b),
+			Div(a, b) => binary(analyzer, BinaryOp::Div, a, b),
+			Mod(a, b) => binary(analyzer, BinaryOp::Rem, a, b),
+			LessThan(a, b) => binary(analyzer, BinaryOp::Compare(Comparison::Less), a, b),
+			LessOrEqual(a, b) => binary(analyzer, BinaryOp::Compare(Comparison::LessOrEqual), a, b),
+			GreaterThan(a, b) => binary(analyzer, BinaryOp::Compare(Comparison::Greater), a, b),
+			GreaterOrEqual(a, b) => binary(
+				analyzer,
+				BinaryOp::Compare(Comparison::GreaterOrEqual),
+				a,
+				b,
+			),
+			Equal(a, b) => binary(analyzer, BinaryOp::Compare(Comparison::Equal), a, b),
+			NotEqual(a, b) => binary(analyzer, BinaryOp::Compare(Comparison::NotEqual), a, b),
+
+			//Anything that calls a function or changes a variable isn't a constant.
+			FuncCall(_, _)
+			| Assign(_, _)
+			| AddAssign(_, _)
+			| SubAssign(_, _)
+			| MulAssign(_, _)
+			| DivAssign(_, _)
+			| ModAssign(_, _) => None,
+		}
+	}
+}
+
+fn binary(analyzer: &Analyzer, op: BinaryOp, a: &Expression, b: &Expression) -> Option<(i16, i64)> {
+	let a = a.constant(analyzer)?;
+	let b = b.constant(analyzer)?;
+	let (wide_a, wide_b) = (a as i64, b as i64);
+
+	let exact = match op {
+		BinaryOp::Add => wide_a + wide_b,
+		BinaryOp::Sub => wide_a - wide_b,
+		BinaryOp::Mul => wide_a * wide_b,
+		//Dividing by a constant zero is an error, so don't pretend to know the result.
+		BinaryOp::Div | BinaryOp::Rem if b == 0 => return None,
+		BinaryOp::Div => wide_a / wide_b,
+		BinaryOp::Rem => wide_a % wide_b,
+		//These only appear after optimization, and can't overflow anyway.
+		BinaryOp::ShiftLeft | BinaryOp::ShiftRight | BinaryOp::And => op.apply(a, b) as i64,
+		BinaryOp::Compare(comparison) => comparison.holds(a, b) as i64,
+	};
+	Some((op.apply(a, b), exact))
+}
@@ -9,29 +9,24 @@ use crate::semantics::Analyzer;
 impl Statement {
 	fn hint_function_signature(&self, expr: &Expression, analyzer: &Analyzer) {
 		//If the expression is a function, try to print a hint about its signature.
-		match &expr.node {
-			FuncCall(name, _) => match &name.node {
-				Var(id) => match analyzer.get_function(id) {
-					None => {}
-					Some(func) => {
-						message::hint(
-							format!("Function signature is `{}{}`", id, func),
-							Some(expr.span),
-							Some(analyzer.context),
-						);
-					}
-				},
-				_ => {}
-			},
-			_ => {}
+		if let FuncCall(name, _) = &expr.node {
+			if let Var(id) = &name.node {
+				if let Some(func) = analyzer.get_function(id) {
+					message::hint(
+						format!("Function signature is `{}{}`", id, func),
+						Some(expr.span),
+						Some(analyzer.context),
+					);
+				}
+			}
 		}
 	}
 
-	pub fn analyze(&self, analyzer: &mut Analyzer) -> bool {
+	pub fn analyze(&self, analyzer: &mut Analyzer) {
 		//Make sure everything is in the correct scope
 		match &self.node {
-			FuncDecl(_, _, _, _) => {
-				if analyzer.func_stack.len() > 0 {
+			FuncDecl(_, _, _, _, _, _) => {
+				if !analyzer.func_stack.is_empty() {
 					message::error(
 						"Functions cannot be declared inside other functions".to_string(),
 						Some(self.span),
@@ -40,13 +35,13 @@ impl Statement {
 				}
 			}
 			_ => {
-				if analyzer.func_stack.len() == 0 {
+				if analyzer.func_stack.is_empty() {
 					message::error(
 						"This statement must be inside a function".to_string(),
 						Some(self.span),
 						Some(analyzer.context),
 					);
-					return false;
+					return;
 				}
 			}
 		}
@@ -56,7 +51,23 @@ impl Statement {
 				expr.analyze(analyzer);
 			}
 
-			FuncDecl(name, params, return_type, body) => {
+			FuncDecl(name, params, return_type, body, attributes, kind) => {
+				let test = match kind.as_ref() {
+					Some(word) if word.value == "test" => true,
+					Some(word) => {
+						message::error(
+							format!(
+								"Unknown kind of function `{}`. Only `test funk` is allowed",
+								word.value
+							),
+							Some(word.span),
+							Some(analyzer.context),
+						);
+						false
+					}
+					None => false,
+				};
+
 				match analyzer.get_function(&name.value) {
 					Some(_) => {
 						message::error(
@@ -68,9 +79,25 @@ impl Statement {
 					None => {
 						let params = params.iter().map(|s| s.datatype.value.clone()).collect();
 						analyzer.set_function(&name.value, params, &return_type.value.clone());
+						if test {
+							analyzer.set_test(&name.value);
+						}
 					}
 				}
 
+				//The test runner has nothing to pass in, and nothing to do with a result.
+				if test && (!params.is_empty() || return_type.value != Analyzer::VOID) {
+					message::error(
+						format!(
+							"Test `{}` must have the signature `() -> {}`",
+							name.value,
+							Analyzer::VOID
+						),
+						Some(name.span),
+						Some(analyzer.context),
+					);
+				}
+
 				if !analyzer.valid_return_type(&return_type.value) {
 					message::error(
 						format!(
@@ -84,15 +111,39 @@ impl Statement {
 					);
 				}
 
+				for attribute in attributes.iter() {
+					if attribute.value != "inline" && attribute.value != "noinline" {
+						message::error(
+							format!(
+								"Unknown attribute `@{}`. Valid attributes are `@inline` or `@noinline`",
+								attribute.value
+							),
+							Some(attribute.span),
+							Some(analyzer.context),
+						);
+					}
+				}
+				let has = |value: &str| attributes.iter().any(|attribute| attribute.value == value);
+				if has("inline") && has("noinline") {
+					message::error(
+						format!(
+							"Function `{}` cannot be both `@inline` and `@noinline`",
+							name.value
+						),
+						Some(name.span),
+						Some(analyzer.context),
+					);
+				}
+
 				if name.value == Analyzer::FUNC_MAIN {
 					//Force the main() function to have a specific signature
-					if params.len() > 0 || return_type.value != Analyzer::VOID {
-						let span = if params.len() > 0 && return_type.value != Analyzer::VOID {
+					if !params.is_empty() || return_type.value != Analyzer::VOID {
+						let span = if !params.is_empty() && return_type.value != Analyzer::VOID {
 							crate::lexer::Span {
 								lo: params[0].span.lo,
 								hi: return_type.span.hi,
 							}
-						} else if params.len() > 0 {
+						} else if !params.is_empty() {
 							crate::lexer::Span {
 								lo: params[0].span.lo,
 								hi: params[params.len() - 1].span.hi,
@@ -114,7 +165,7 @@ impl Statement {
 					//Also only allow it to be declared in the global scope
 					if analyzer.scopes.len() > 1 {
 						message::error(
-							format!("Function `main` may only be declared in the global scope"),
+							"Function `main` may only be declared in the global scope".to_string(),
 							Some(name.span),
 							Some(analyzer.context),
 						);
@@ -143,31 +194,30 @@ impl Statement {
 					);
 				}
 
-				let return_guaranteed = body.analyze(analyzer);
+				body.analyze(analyzer);
 
 				analyzer.func_stack.pop();
 				let scope = analyzer.pop_scope();
 
-				//Check for any mutable variables that don't have to be
-				for (name, signature) in scope.variables {
-					if signature.used == 0 && !name.starts_with("_") {
-						if !analyzer.flags.warn_suppress {
-							message::warning(format!("Variable `{name}` is never used. If this is intentional, prefix the variable name with an underscore (e.g. `_{name}`)"), Some(signature.span), Some(analyzer.context));
-						}
+				//Check for any mutable variables that don't have to be, in the order they were
+				//declared so the warnings always come out the same way.
+				let mut variables: Vec<_> = scope.variables.into_iter().collect();
+				variables.sort_by_key(|(_, signature)| signature.span.lo);
+				for (name, signature) in variables {
+					if signature.used == 0
+						&& !name.starts_with("_")
+						&& !analyzer.flags.warn_suppress
+					{
+						message::warning(format!("Variable `{name}` is never used. If this is intentional, prefix the variable name with an underscore (e.g. `_{name}`)"), Some(signature.span), Some(analyzer.context));
 					}
-					if signature.mutable && signature.changed == 0 {
-						if !analyzer.flags.warn_suppress {
-							message::warning(format!("Variable `{name}` does not need to be mutable. Consider replacing `let` with `set`"), Some(signature.span), Some(analyzer.context));
-						}
+					if signature.mutable && signature.changed == 0 && !analyzer.flags.warn_suppress
+					{
+						message::warning(format!("Variable `{name}` does not need to be mutable. Consider replacing `let` with `set`"), Some(signature.span), Some(analyzer.context));
 					}
 				}
 
-				if name.value != Analyzer::FUNC_MAIN
-					&& return_type.value != Analyzer::VOID
-					&& !return_guaranteed
-				{
-					message::error(format!("Function `{}` might not return a value. A value of type `{}` must always be returned", name.value, return_type.value), Some(self.span), Some(analyzer.context));
-				}
+				//Checking that a value is always returned needs to know how control flows, so it's
+				//done later by `check_flow()`.
 			}
 
 			VarDecl(qualifiers, name, datatype, value) => {
@@ -194,7 +244,7 @@ impl Statement {
 							Some(var.span),
 							Some(analyzer.context),
 						);
-						return false;
+						return;
 					}
 				}
 
@@ -203,6 +253,13 @@ impl Statement {
 					Immutable => false,
 				};
 
+				//Work this out before the variable is declared, in case it shadows something the value uses.
+				let constant = if mutable {
+					None
+				} else {
+					value.constant(analyzer)
+				};
+
 				if !mutable && analyzer.flags.language_server {
 					message::diagnostic(
 						message::DiagnosticType::Constant,
@@ -247,6 +304,11 @@ impl Statement {
 						analyzer.set_variable(&name.value, &data_type.value, mutable, name.span);
 					}
 				}
+
+				//Immutable variables with constant values can be folded into the expressions that use them.
+				if let Some(value) = constant {
+					analyzer.set_constant(&name.value, value);
+				}
 			}
 
 			ReturnStmt(expr) => {
@@ -257,7 +319,7 @@ impl Statement {
 					None => {
 						if func.return_type != Analyzer::VOID {
 							message::error(
-								format!("Return statement must have a value"),
+								"Return statement must have a value".to_string(),
 								Some(self.span),
 								Some(analyzer.context),
 							);
@@ -271,7 +333,7 @@ impl Statement {
 					Some(ref expr) => {
 						if func.return_type == Analyzer::VOID {
 							message::error(
-								format!("Return statement cannot have a value"),
+								"Return statement cannot have a value".to_string(),
 								Some(expr.span),
 								Some(analyzer.context),
 							);
@@ -293,8 +355,6 @@ impl Statement {
 						}
 					}
 				}
-
-				return true;
 			}
 
 			IfStmt(condition, stmts_true, stmts_false) => {
@@ -308,14 +368,12 @@ impl Statement {
 					self.hint_function_signature(condition, analyzer);
 				}
 
-				//If statements are only guaranteed to return if all the branches are also guaranteed to return.
 				analyzer.push_scope();
-				let true_branch = stmts_true.analyze(analyzer);
+				stmts_true.analyze(analyzer);
 				analyzer.pop_scope();
 				analyzer.push_scope();
-				let false_branch = stmts_false.analyze(analyzer);
+				stmts_false.analyze(analyzer);
 				analyzer.pop_scope();
-				return true_branch && false_branch;
 			}
 
 			WhileStmt(condition, stmts) => {
@@ -334,8 +392,6 @@ impl Statement {
 				stmts.analyze(analyzer);
 				analyzer.loops -= 1;
 				analyzer.pop_scope();
-
-				//Note: while loops are never really guaranteed to run. they might not.
 			}
 
 			BreakStmt => {
@@ -358,7 +414,5 @@ impl Statement {
 				}
 			}
 		}
-
-		return false;
 	}
 }
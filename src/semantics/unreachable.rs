@@ -0,0 +1,56 @@
+use crate::lexer::Span;
+use crate::message;
+use crate::parser::ast::Stmt::*;
+use crate::parser::ast::{Program, Statement};
+use crate::semantics::Analyzer;
+
+impl Program {
+	/// Whether running these statements always ends in `ret`, `break` or `continue`,
+	/// so that nothing after them can run.
+	pub fn always_leaves(&self) -> bool {
+		self.stmts.iter().any(|stmt| stmt.always_leaves())
+	}
+
+	/// Warn about statements that come after one that always leaves the block.
+	/// They're still analyzed, but no code is generated for them.
+	pub fn check_unreachable(&self, analyzer: &Analyzer) {
+		let position = match self.stmts.iter().position(|stmt| stmt.always_leaves()) {
+			Some(position) if position + 1 < self.stmts.len() => position,
+			_ => return,
+		};
+		if analyzer.flags.warn_suppress {
+			return;
+		}
+
+		let span = Span {
+			lo: self.stmts[position + 1].span.lo,
+			hi: self.stmts[self.stmts.len() - 1].span.hi,
+		};
+		message::warning(
+			"Unreachable code. It will never run, so it has been left out".to_string(),
+			Some(span),
+			Some(analyzer.context),
+		);
+		message::hint(
+			"Nothing after this statement can run".to_string(),
+			Some(self.stmts[position].span),
+			Some(analyzer.context),
+		);
+	}
+}
+
+impl Statement {
+	/// Whether this statement always ends in `ret`, `break` or `continue`.
+	/// Conditions aren't looked at, so `while 1 { }` doesn't count.
+	pub fn always_leaves(&self) -> bool {
+		match &self.node {
+			ReturnStmt(_) | BreakStmt | ContinueStmt => true,
+			IfStmt(_, stmts_true, stmts_false) => {
+				stmts_true.always_leaves() && stmts_false.always_leaves()
+			}
+			ExprStmt(_) | VarDecl(_, _, _, _) | WhileStmt(_, _) | FuncDecl(_, _, _, _, _, _) => {
+				false
+			}
+		}
+	}
+}
@@ -0,0 +1,193 @@
+//Everything the compiler needs to know about the computer a program will run on.
+
+mod breadboard;
+mod c64;
+mod config;
+
+use crate::codegen::asm::Bytecode::{self, *};
+use crate::codegen::asm::Mode::*;
+use crate::codegen::asm::Value::{self, *};
+use crate::emulator::{self, Hardware};
+use crate::message;
+use std::fs;
+use std::path::Path;
+
+/// Label of the first instruction that runs, which the reset vector points to.
+pub const RESET: &str = "TARGET_reset";
+/// Label of the routine that handles NMI and IRQ, if the target has vectors.
+pub const INTERRUPT: &str = "TARGET_interrupt";
+/// Label of the routine that prints the character in A.
+pub const PRINT_CHAR: &str = "TARGET_print_char";
+/// Label of the routine that ends a line of output.
+pub const PRINT_NEWLINE: &str = "TARGET_print_newline";
+/// Label of the routine that runs when an assertion doesn't hold, with A set to 1 for `assert`
+/// and 2 for `assert_eq`, or when the program runs out of stack, with A set to `OUT_OF_STACK`.
+/// It never returns.
+pub const ASSERT_FAILED: &str = "TARGET_assert_failed";
+/// What A is set to when `ASSERT_FAILED` runs because the program ran out of stack.
+pub const OUT_OF_STACK: u16 = 3;
+/// Symbol for where a failed assertion leaves its details, if the target wants them.
+pub const ASSERT_DETAILS: &str = "ASSERT_DETAILS";
+
+/// How a target shows the output of `print`. Each routine can change any register.
+/// Code written for a target should refer to hardware registers by symbol (`Define`), so the
+/// peephole optimizer doesn't mistake them for the compiler's own memory.
+pub struct Print {
+	/// Print the character in A.
+	pub char: Vec<Bytecode>,
+	/// End the line.
+	pub newline: Vec<Bytecode>,
+}
+
+/// A computer that programs can be built for.
+pub struct Target {
+	pub name: String,
+	/// Where the program image goes, inclusive. Code starts at the first address.
+	pub rom: (u16, u16),
+	/// Where variables and the software stack go, inclusive. The stack grows down from the end.
+	pub ram: (u16, u16),
+	/// Zero page the compiler has to leave alone, inclusive.
+	pub zp_reserved: Vec<(u8, u8)>,
+	/// Where the NMI, reset and IRQ vectors go, if the program owns them.
+	pub vectors: Option<u16>,
+	/// Goes at the very start of the image, before any code.
+	pub header: Vec<Bytecode>,
+	/// Whether the image starts with the address it loads at, as Commodore PRG files do.
+	pub load_address: bool,
+	/// Runs first, to set up the CPU and hardware.
+	pub startup: Vec<Bytecode>,
+	/// Runs when the entry point returns.
+	pub exit: Vec<Bytecode>,
+	/// How to print, if the target can.
+	pub print: Option<Print>,
+	/// What to do when an assertion fails (see `ASSERT_FAILED`).
+	pub assert_failed: Vec<Bytecode>,
+	/// Where a failed assertion leaves its span (two words), then the values that `assert_eq`
+	/// compared (two more words). If this is None, they aren't kept.
+	pub assert_details: Option<u16>,
+	/// Anything else the startup code or builtins need, included as-is.
+	pub runtime: Vec<Bytecode>,
+	/// How to run programs on the emulator (`--run`), if it can stand in for the computer.
+	pub hardware: Option<Hardware>,
+}
+
+/// Names of the targets built into the compiler.
+pub const BUILTIN: [&str; 3] = ["emulator", "breadboard", "c64"];
+
+impl Target {
+	/// Look up a built-in target by name, or read a target description from a file.
+	pub fn find(name: &str) -> Result<Target, String> {
+		match name {
+			"emulator" => return Ok(emulator::target()),
+			"breadboard" => return Ok(breadboard::target()),
+			"c64" => return Ok(c64::target()),
+			_ => {}
+		}
+
+		let path = Path::new(name);
+		if !path.exists() {
+			return Err(format!(
+				"`{}` is not a target. Built-in targets are {}, or give the path of a target file",
+				name,
+				BUILTIN
+					.iter()
+					.map(|name| format!("`{}`", name))
+					.collect::<Vec<_>>()
+					.join(", ")
+			));
+		}
+
+		let text = match fs::read_to_string(path) {
+			Ok(text) => text,
+			Err(error) => return Err(format!("Error reading target file {:?}: {}", path, error)),
+		};
+		let default_name = path.file_stem().unwrap().to_string_lossy();
+		config::parse(&text, &default_name)
+			.map_err(|error| format!("In target file {:?}: {}", path, error))
+	}
+
+	/// Where the software stack starts, just past the end of RAM.
+	pub fn stack_top(&self) -> u16 {
+		self.ram.1.wrapping_add(1)
+	}
+
+	/// The routines behind the builtins that a program uses, and the vectors if there are any.
+	/// If the target can't print, using `print` is reported as an error.
+	pub fn runtime(&self, prints: bool, asserts: bool) -> Vec<Bytecode> {
+		let mut code = vec![];
+
+		if prints {
+			match &self.print {
+				Some(print) => {
+					code.push(Label(PRINT_CHAR.to_string()));
+					code.extend(print.char.iter().cloned());
+					code.push(Label(PRINT_NEWLINE.to_string()));
+					code.extend(print.newline.iter().cloned());
+				}
+				None => message::error(
+					format!("Target `{}` has no way to `print`", self.name),
+					None,
+					None,
+				),
+			}
+		}
+
+		if asserts {
+			if let Some(address) = self.assert_details {
+				code.push(Define(ASSERT_DETAILS.to_string(), Number(address)));
+			}
+			code.push(Label(ASSERT_FAILED.to_string()));
+			code.extend(self.assert_failed.iter().cloned());
+		}
+
+		code.extend(self.runtime.iter().cloned());
+
+		if let Some(address) = self.vectors {
+			let interrupt = || Value::symbol(INTERRUPT);
+			code.extend([
+				Label(INTERRUPT.to_string()),
+				RTI(Implied),
+				Org(address),
+				Word(vec![interrupt(), Value::symbol(RESET), interrupt()]),
+			]);
+		}
+		code
+	}
+
+	/// Assemble a program into an image of the target's ROM, making sure it fits.
+	pub fn assemble(&self, bytecode: Vec<Bytecode>) -> Vec<u8> {
+		let mut image = Bytecode::output_binary(bytecode, self.rom.0);
+		let room = self.rom.1 as usize - self.rom.0 as usize + 1;
+		if image.len() > room {
+			message::error(
+				format!(
+					"Program is {} bytes, but target `{}` only has room for {}",
+					image.len(),
+					self.name,
+					room
+				),
+				None,
+				None,
+			);
+		}
+
+		if self.load_address {
+			image.splice(0..0, self.rom.0.to_le_bytes());
+		}
+		image
+	}
+}
+
+/// Code that stops the CPU for good, by jumping to itself.
+pub fn halt(label: &str) -> Vec<Bytecode> {
+	vec![
+		Label(label.to_string()),
+		JMP(Absolute(Value::symbol(label))),
+	]
+}
+
+/// The usual way to start a program that owns the whole computer: set up the hardware stack and
+/// make sure arithmetic is in binary.
+pub fn reset() -> Vec<Bytecode> {
+	vec![LDX(Immediate(Number(0xFF))), TXS(Implied), CLD(Implied)]
+}
@@ -0,0 +1,208 @@
+//The 65C02 breadboard computer: 16K of RAM at $0000, a 6522 VIA at $6000 and a 32K EEPROM at
+//$8000. A 16x2 HD44780 LCD is on the VIA, in 8-bit mode: data on port B, and the E, RW and RS
+//lines on the top three pins of port A.
+
+use super::{halt, reset, Print, Target, OUT_OF_STACK};
+use crate::codegen::asm::Bytecode::{self, *};
+use crate::codegen::asm::Mode::*;
+use crate::codegen::asm::Value::{self, *};
+use crate::emulator::{Device, Hardware, Region};
+
+const VIA: u16 = 0x6000;
+//Port A control lines.
+const E: u16 = 0b1000_0000;
+const RW: u16 = 0b0100_0000;
+const RS: u16 = 0b0010_0000;
+//Display addresses of the start of each line.
+const LINE_2: u16 = 0x40;
+//LCD instructions.
+const CLEAR: u16 = 0b0000_0001;
+const SET_ADDRESS: u16 = 0b1000_0000;
+//What a failure shows, one after the other in ROM.
+const ASSERTION_FAILED: &str = "Assertion failed\0";
+const OUT_OF_STACK_TEXT: &str = "Out of stack\0";
+
+fn label(name: &str) -> Bytecode {
+	Label(name.to_string())
+}
+
+fn symbol(name: &str) -> Value {
+	Value::symbol(name)
+}
+
+fn jsr(name: &str) -> Bytecode {
+	JSR(Absolute(symbol(name)))
+}
+
+//Pulse E with the given control lines, so the LCD takes what's on port B.
+fn pulse(lines: u16) -> [Bytecode; 6] {
+	[
+		LDA(Immediate(Number(lines))),
+		STA(Absolute(symbol("VIA_PORTA"))),
+		LDA(Immediate(Number(lines | E))),
+		STA(Absolute(symbol("VIA_PORTA"))),
+		LDA(Immediate(Number(lines))),
+		STA(Absolute(symbol("VIA_PORTA"))),
+	]
+}
+
+/// The breadboard computer as a target. `print` writes to the LCD, one number per line: the
+/// first line, then the second, then it clears and starts again. A failed assertion, or running
+/// out of stack, says so on the LCD and halts. On the emulator, what the LCD shows is printed.
+pub fn target() -> Target {
+	let mut runtime = vec![
+		Define("VIA_PORTB".to_string(), Number(VIA)),
+		Define("VIA_PORTA".to_string(), Number(VIA + 1)),
+		Define("VIA_DDRB".to_string(), Number(VIA + 2)),
+		Define("VIA_DDRA".to_string(), Number(VIA + 3)),
+		//Whether a line has ended, so the next character has to start a new one.
+		//It's kept just past the end of RAM that the compiler uses.
+		Define("LCD_NEWLINE".to_string(), Number(0x3FFF)),
+		//Set up the VIA and the LCD.
+		label("LCD_init"),
+		LDA(Immediate(Number(0xFF))),
+		STA(Absolute(symbol("VIA_DDRB"))),
+		LDA(Immediate(Number(E | RW | RS))),
+		STA(Absolute(symbol("VIA_DDRA"))),
+		//8-bit interface, 2 lines, 5x8 font
+		LDA(Immediate(Number(0b0011_1000))),
+		jsr("LCD_instruction"),
+		//Display on, cursor off
+		LDA(Immediate(Number(0b0000_1100))),
+		jsr("LCD_instruction"),
+		//Move the cursor right after each character, without scrolling
+		LDA(Immediate(Number(0b0000_0110))),
+		jsr("LCD_instruction"),
+		LDA(Immediate(Number(CLEAR))),
+		jsr("LCD_instruction"),
+		LDA(Immediate(Number(0))),
+		STA(Absolute(symbol("LCD_NEWLINE"))),
+		RTS(Implied),
+		//Wait until the LCD isn't busy, leaving its cursor address in A.
+		//Port B has to be switched to input to read it.
+		label("LCD_wait"),
+		LDA(Immediate(Number(0))),
+		STA(Absolute(symbol("VIA_DDRB"))),
+		label("LCD_wait_busy"),
+		LDA(Immediate(Number(RW))),
+		STA(Absolute(symbol("VIA_PORTA"))),
+		LDA(Immediate(Number(RW | E))),
+		STA(Absolute(symbol("VIA_PORTA"))),
+		LDA(Absolute(symbol("VIA_PORTB"))),
+		BMI(Relative(symbol("LCD_wait_busy"))),
+		PHA(Implied),
+		LDA(Immediate(Number(RW))),
+		STA(Absolute(symbol("VIA_PORTA"))),
+		LDA(Immediate(Number(0xFF))),
+		STA(Absolute(symbol("VIA_DDRB"))),
+		PLA(Implied),
+		RTS(Implied),
+		//Send the instruction in A.
+		label("LCD_instruction"),
+		PHA(Implied),
+		jsr("LCD_wait"),
+		PLA(Implied),
+		STA(Absolute(symbol("VIA_PORTB"))),
+	];
+	runtime.extend(pulse(0));
+	runtime.extend([
+		RTS(Implied),
+		//Show the character in A. Leaves X and Y alone.
+		label("LCD_write"),
+		PHA(Implied),
+		jsr("LCD_wait"),
+		PLA(Implied),
+		STA(Absolute(symbol("VIA_PORTB"))),
+	]);
+	runtime.extend(pulse(RS));
+	runtime.push(RTS(Implied));
+
+	Target {
+		name: "breadboard".to_string(),
+		rom: (0x8000, 0xFFFF),
+		ram: (0x0200, 0x3FFE),
+		zp_reserved: vec![],
+		vectors: Some(0xFFFA),
+		header: vec![],
+		load_address: false,
+		startup: reset().into_iter().chain([jsr("LCD_init")]).collect(),
+		exit: halt("halt"),
+		print: Some(Print {
+			char: vec![
+				LDX(Absolute(symbol("LCD_NEWLINE"))),
+				BEQ(Relative(symbol("TARGET_print_char_write"))),
+				PHA(Implied),
+				LDA(Immediate(Number(0))),
+				STA(Absolute(symbol("LCD_NEWLINE"))),
+				//Go on to the second line, or clear the display if that's where the cursor is.
+				jsr("LCD_wait"),
+				CMP(Immediate(Number(LINE_2))),
+				LDA(Immediate(Number(SET_ADDRESS | LINE_2))),
+				BCC(Relative(symbol("TARGET_print_char_move"))),
+				LDA(Immediate(Number(CLEAR))),
+				label("TARGET_print_char_move"),
+				jsr("LCD_instruction"),
+				PLA(Implied),
+				label("TARGET_print_char_write"),
+				JMP(Absolute(symbol("LCD_write"))),
+			],
+			//Ending the line straight away would clear the second line before anyone could
+			//read it, so it waits for the next character.
+			newline: vec![
+				LDA(Immediate(Number(1))),
+				STA(Absolute(symbol("LCD_NEWLINE"))),
+				RTS(Implied),
+			],
+		}),
+		assert_failed: [
+			LDX(Immediate(Number(0))),
+			CMP(Immediate(Number(OUT_OF_STACK))),
+			BNE(Relative(symbol("TARGET_assert_failed_clear"))),
+			LDX(Immediate(Number(ASSERTION_FAILED.len() as u16))),
+			label("TARGET_assert_failed_clear"),
+			LDA(Immediate(Number(CLEAR))),
+			jsr("LCD_instruction"),
+			label("TARGET_assert_failed_char"),
+			LDA(AbsoluteX(symbol("TARGET_assert_failed_text"))),
+			BEQ(Relative(symbol("TARGET_assert_failed_halt"))),
+			jsr("LCD_write"),
+			INX(Implied),
+			BNE(Relative(symbol("TARGET_assert_failed_char"))),
+		]
+		.into_iter()
+		.chain(halt("TARGET_assert_failed_halt"))
+		.chain([
+			label("TARGET_assert_failed_text"),
+			Byte(
+				[ASSERTION_FAILED, OUT_OF_STACK_TEXT]
+					.concat()
+					.bytes()
+					.map(|byte| Number(byte as u16))
+					.collect(),
+			),
+		])
+		.collect(),
+		assert_details: None,
+		runtime,
+		hardware: Some(Hardware {
+			regions: vec![
+				Region {
+					start: 0x0000,
+					end: 0x3FFF,
+					device: Device::Ram,
+				},
+				Region {
+					start: VIA,
+					end: VIA + 0x0F,
+					device: Device::Lcd,
+				},
+				Region {
+					start: 0x8000,
+					end: 0xFFFF,
+					device: Device::Rom,
+				},
+			],
+			firmware: None,
+		}),
+	}
+}
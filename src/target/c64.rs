@@ -0,0 +1,166 @@
+//The Commodore 64, with programs loaded from a PRG file and started from BASIC with `RUN`.
+//The program goes where BASIC programs go, starting with a one-line BASIC program that calls it:
+//
+//	10 SYS 2061
+//
+//BASIC and the KERNAL use nearly all of zero page, and the program has to leave everything they
+//need alone so it can return to BASIC when it's done.
+
+use super::{Print, Target, OUT_OF_STACK};
+use crate::codegen::asm::Bytecode::{self, *};
+use crate::codegen::asm::Mode::*;
+use crate::codegen::asm::Value::{self, *};
+use crate::emulator::{Device, Hardware, Region};
+
+//Where BASIC programs are loaded.
+const BASIC_START: u16 = 0x0801;
+//Where the program itself starts, after the BASIC program. Everything in that but the 4 digits of
+//the address takes 8 bytes.
+const START: u16 = BASIC_START + 8 + 4;
+//The KERNAL routine that prints the character in A.
+const CHROUT: u16 = 0xFFD2;
+//Carriage return, which ends a line in PETSCII.
+const RETURN: u16 = 13;
+//The `SYS` token in tokenized BASIC.
+const SYS: u16 = 0x9E;
+//What a failure prints, one after the other in memory.
+const ASSERTION_FAILED: &str = "ASSERTION FAILED\r\0";
+const OUT_OF_STACK_TEXT: &str = "OUT OF STACK\r\0";
+
+//The BASIC program that starts the real one: a pointer to the next line, the line number, then
+//the tokenized line itself. A next line of zero marks the end of the program.
+fn basic_stub() -> Vec<Bytecode> {
+	let next_line = START - 2;
+
+	let mut line = vec![
+		Number(next_line & 0xFF),
+		Number(next_line >> 8),
+		Number(10),
+		Number(0),
+		Number(SYS),
+	];
+	line.extend(START.to_string().bytes().map(|digit| Number(digit as u16)));
+	line.extend([Number(0), Number(0), Number(0)]);
+	vec![Byte(line)]
+}
+
+//Where the emulator prints what `CHROUT` is given. Nothing is there on a real C64.
+const EMULATOR_OUTPUT: u16 = 0xDE00;
+
+//Just enough of the KERNAL for the emulator to run a program: starting it as `SYS` would, and
+//`CHROUT`, which prints its character with the PETSCII line ending turned into a newline.
+fn kernal() -> Vec<Bytecode> {
+	let symbol = Value::symbol;
+	vec![
+		//Where the real KERNAL's reset routine is.
+		Org(0xFCE2),
+		Label("KERNAL_reset".to_string()),
+		LDX(Immediate(Number(0xFF))),
+		TXS(Implied),
+		JSR(Absolute(Number(START))),
+		Label("KERNAL_halt".to_string()),
+		JMP(Absolute(symbol("KERNAL_halt"))),
+		Label("KERNAL_chrout".to_string()),
+		CMP(Immediate(Number(RETURN))),
+		BNE(Relative(symbol("KERNAL_chrout_output"))),
+		LDA(Immediate(Number('\n' as u16))),
+		STA(Absolute(Number(EMULATOR_OUTPUT))),
+		LDA(Immediate(Number(RETURN))),
+		RTS(Implied),
+		Label("KERNAL_chrout_output".to_string()),
+		STA(Absolute(Number(EMULATOR_OUTPUT))),
+		RTS(Implied),
+		Org(CHROUT),
+		JMP(Absolute(symbol("KERNAL_chrout"))),
+		Org(0xFFFA),
+		Word(vec![
+			symbol("KERNAL_reset"),
+			symbol("KERNAL_reset"),
+			symbol("KERNAL_reset"),
+		]),
+	]
+}
+
+/// The C64 as a target. `print` goes through the KERNAL's `CHROUT`, so it shows up on screen
+/// like BASIC's `PRINT`. The program returns to BASIC when `main` ends, or when an assertion
+/// fails or it runs out of stack, after saying so. On the emulator, what it prints goes to the output.
+pub fn target() -> Target {
+	let saved_stack = || Value::symbol("C64_SAVED_STACK");
+	let chrout = || JMP(Absolute(Value::symbol("C64_CHROUT")));
+
+	Target {
+		name: "c64".to_string(),
+		//Up to where BASIC ROM starts.
+		rom: (BASIC_START, 0x9FFF),
+		//The 4K of RAM at $C000 isn't used by BASIC or the KERNAL.
+		ram: (0xC000, 0xCFFE),
+		//$02 and $FB-$FE are free. $57-$60 is where BASIC does floating point arithmetic, which
+		//it doesn't need kept once `SYS` has started the program.
+		zp_reserved: vec![(0x00, 0x01), (0x03, 0x56), (0x61, 0xFA), (0xFF, 0xFF)],
+		vectors: None,
+		header: basic_stub(),
+		load_address: true,
+		//Remember where BASIC's stack was, so the program can get back to it from anywhere.
+		startup: vec![CLD(Implied), TSX(Implied), STX(Absolute(saved_stack()))],
+		exit: vec![RTS(Implied)],
+		print: Some(Print {
+			char: vec![chrout()],
+			newline: vec![LDA(Immediate(Number(RETURN))), chrout()],
+		}),
+		assert_failed: vec![
+			LDX(Immediate(Number(0))),
+			CMP(Immediate(Number(OUT_OF_STACK))),
+			BNE(Relative(Value::symbol("TARGET_assert_failed_char"))),
+			LDX(Immediate(Number(ASSERTION_FAILED.len() as u16))),
+			Label("TARGET_assert_failed_char".to_string()),
+			LDA(AbsoluteX(Value::symbol("TARGET_assert_failed_text"))),
+			BEQ(Relative(Value::symbol("TARGET_assert_failed_exit"))),
+			JSR(Absolute(Value::symbol("C64_CHROUT"))),
+			INX(Implied),
+			BNE(Relative(Value::symbol("TARGET_assert_failed_char"))),
+			Label("TARGET_assert_failed_exit".to_string()),
+			LDX(Absolute(saved_stack())),
+			TXS(Implied),
+			RTS(Implied),
+			Label("TARGET_assert_failed_text".to_string()),
+			Byte(
+				[ASSERTION_FAILED, OUT_OF_STACK_TEXT]
+					.concat()
+					.bytes()
+					.map(|byte| Number(byte as u16))
+					.collect(),
+			),
+		],
+		assert_details: None,
+		runtime: vec![
+			Define("C64_CHROUT".to_string(), Number(CHROUT)),
+			Define("C64_SAVED_STACK".to_string(), Number(0xCFFF)),
+		],
+		hardware: Some(Hardware {
+			regions: vec![
+				//BASIC's ROM isn't needed, so it's left out.
+				Region {
+					start: 0x0000,
+					end: 0x9FFF,
+					device: Device::Ram,
+				},
+				Region {
+					start: 0xC000,
+					end: 0xCFFF,
+					device: Device::Ram,
+				},
+				Region {
+					start: EMULATOR_OUTPUT,
+					end: EMULATOR_OUTPUT,
+					device: Device::Output,
+				},
+				Region {
+					start: 0xE000,
+					end: 0xFFFF,
+					device: Device::Rom,
+				},
+			],
+			firmware: Some((0xE000, kernal())),
+		}),
+	}
+}
@@ -0,0 +1,154 @@
+//Target files describe a computer in lines of `key = value`, with `#` starting a comment:
+//
+//	# 8K of EEPROM at the top of memory, and a monitor that can print characters.
+//	name = homebrew
+//	rom = $E000-$FFFF
+//	ram = $0200-$3FFF
+//	zp_reserve = $00-$0F
+//	vectors = $FFFA
+//	print_char = $F000
+//	newline = 13
+//
+//`rom` and `ram` are required. `zp_reserve` can be given more than once.
+
+use super::{halt, reset, Print, Target};
+use crate::codegen::asm::Bytecode::*;
+use crate::codegen::asm::Mode::*;
+use crate::codegen::asm::Value::{self, *};
+use crate::flags::parse_zp_range;
+
+//Symbol for the board's own routine that prints a character.
+const BOARD_PRINT_CHAR: &str = "BOARD_print_char";
+
+//Parse an address like `0xE000`, `$E000` or `57344`.
+fn parse_address(text: &str) -> Result<u16, String> {
+	let text = text.trim();
+	let value = if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix('$')) {
+		u16::from_str_radix(hex, 16)
+	} else {
+		text.parse::<u16>()
+	};
+	value.map_err(|_| format!("`{}` is not a valid address", text))
+}
+
+//Parse an inclusive range of addresses like `$0200-$3FFF`.
+fn parse_range(text: &str) -> Result<(u16, u16), String> {
+	let (first, last) = match text.split_once('-') {
+		Some((first, last)) => (parse_address(first)?, parse_address(last)?),
+		None => return Err(format!("`{}` is not a range like `$0200-$3FFF`", text)),
+	};
+
+	if first > last {
+		return Err(format!("`{}` is an empty range", text));
+	}
+	Ok((first, last))
+}
+
+/// Read a target description, naming it after the file unless it says otherwise.
+pub fn parse(text: &str, default_name: &str) -> Result<Target, String> {
+	let mut name = default_name.to_string();
+	let mut rom = None;
+	let mut ram = None;
+	let mut zp_reserved = vec![];
+	let mut vectors = None;
+	let mut print_char = None;
+	let mut newline = b'\n';
+	let mut exit = None;
+	let mut assert_details = None;
+
+	for (index, line) in text.lines().enumerate() {
+		let line = match line.split_once('#') {
+			Some((line, _)) => line.trim(),
+			None => line.trim(),
+		};
+		if line.is_empty() {
+			continue;
+		}
+
+		let at_line = |error: String| format!("line {}: {}", index + 1, error);
+		let (key, value) = match line.split_once('=') {
+			Some((key, value)) => (key.trim(), value.trim()),
+			None => return Err(at_line(format!("Expected `key = value`, got `{}`", line))),
+		};
+
+		match key {
+			"name" => name = value.to_string(),
+			"rom" => rom = Some(parse_range(value).map_err(at_line)?),
+			"ram" => ram = Some(parse_range(value).map_err(at_line)?),
+			"zp_reserve" => zp_reserved.push(parse_zp_range(value).map_err(at_line)?),
+			"vectors" => vectors = Some(parse_address(value).map_err(at_line)?),
+			"print_char" => print_char = Some(parse_address(value).map_err(at_line)?),
+			"newline" => {
+				newline = match value.parse::<u8>() {
+					Ok(newline) => newline,
+					Err(_) => return Err(at_line(format!("`{}` is not a character code", value))),
+				}
+			}
+			"exit" => {
+				exit = match value {
+					"halt" => Some(true),
+					"return" => Some(false),
+					_ => {
+						return Err(at_line(format!(
+							"`{}` is not a way to exit (`halt` or `return`)",
+							value
+						)))
+					}
+				}
+			}
+			"assert_details" => assert_details = Some(parse_address(value).map_err(at_line)?),
+			_ => return Err(at_line(format!("Unknown setting `{}`", key))),
+		}
+	}
+
+	let rom = rom.ok_or("No `rom` given")?;
+	let ram = ram.ok_or("No `ram` given")?;
+	if let Some(address) = vectors {
+		if address < rom.0 || address as u32 + 5 > rom.1 as u32 {
+			return Err(format!("Vectors at ${:04X} are not in ROM", address));
+		}
+	}
+
+	//A program with its own vectors owns the computer, and has nowhere to return to.
+	//Otherwise, something else loaded it and has already set things up.
+	let owns_reset = vectors.is_some();
+	let startup = if owns_reset {
+		reset()
+	} else {
+		vec![CLD(Implied)]
+	};
+	let exit = if exit.unwrap_or(owns_reset) {
+		halt("TARGET_halt")
+	} else {
+		vec![RTS(Implied)]
+	};
+
+	let mut runtime = vec![];
+	let print = print_char.map(|address| {
+		runtime.push(Define(BOARD_PRINT_CHAR.to_string(), Number(address)));
+		Print {
+			char: vec![JMP(Absolute(Value::symbol(BOARD_PRINT_CHAR)))],
+			newline: vec![
+				LDA(Immediate(Number(newline as u16))),
+				JMP(Absolute(Value::symbol(BOARD_PRINT_CHAR))),
+			],
+		}
+	});
+
+	Ok(Target {
+		name,
+		rom,
+		ram,
+		zp_reserved,
+		vectors,
+		header: vec![],
+		load_address: false,
+		startup,
+		exit,
+		print,
+		assert_failed: halt("TARGET_assert_halt"),
+		assert_details,
+		runtime,
+		hardware: None,
+	})
+}
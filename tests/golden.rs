@@ -0,0 +1,470 @@
+//Golden tests: every program in `tests/golden` is compiled and run on the emulator at each
+//optimization level, and what it prints and the errors and warnings it gets are checked against
+//annotations in its comments:
+//
+//	print(total); //out: 55
+//	x = 3;        //error: Cannot mutate immutable variable `x`
+//	//warning: Not enough zero page for the arithmetic workspace (...)
+//
+//Taken in order, the `//out:` lines are everything the program should print. An `//error:` or
+//`//warning:` after some code is expected to point at that line; on a line of its own, it's
+//expected to have no location at all. A program with any expected errors has to fail to compile.
+//Any `//flags:` are passed to the compiler as well.
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+const LEVELS: [&str; 3] = ["0", "1", "2"];
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Diagnostic {
+	kind: String,
+	line: Option<usize>,
+	text: String,
+}
+
+struct Expected {
+	flags: Vec<String>,
+	output: String,
+	diagnostics: Vec<Diagnostic>,
+}
+
+fn expectations(source: &str) -> Expected {
+	let mut flags = vec![];
+	let mut output = String::new();
+	let mut diagnostics = vec![];
+
+	for (index, line) in source.lines().enumerate() {
+		let (code, comment) = match line.split_once("//") {
+			Some(split) => split,
+			None => continue,
+		};
+		let (kind, text) = match comment.trim().split_once(':') {
+			Some((kind, text)) => (kind.trim(), text.trim()),
+			None => continue,
+		};
+
+		match kind {
+			"flags" => flags.extend(text.split_whitespace().map(String::from)),
+			"out" => {
+				output += text;
+				output += "\n";
+			}
+			"error" | "warning" => diagnostics.push(Diagnostic {
+				kind: kind.to_string(),
+				line: if code.trim().is_empty() {
+					None
+				} else {
+					Some(index + 1)
+				},
+				text: text.to_string(),
+			}),
+			_ => {}
+		}
+	}
+
+	diagnostics.sort();
+	Expected {
+		flags,
+		output,
+		diagnostics,
+	}
+}
+
+//Errors and warnings as the compiler prints them, each followed by a line like `  --> file:3:5`
+//if it has a location. Hints and other messages aren't checked.
+fn diagnostics(stderr: &str, path: &Path) -> Vec<Diagnostic> {
+	let lines: Vec<&str> = stderr.lines().collect();
+	let mut diagnostics = vec![];
+
+	for (index, line) in lines.iter().enumerate() {
+		let (kind, text) = match line.split_once(": ") {
+			Some((kind, text)) if kind == "error" || kind == "warning" => (kind, text),
+			_ => continue,
+		};
+
+		let location = lines
+			.get(index + 1)
+			.and_then(|next| next.trim().strip_prefix("--> "))
+			.and_then(|location| location.strip_prefix(&format!("{}:", path.display())))
+			.and_then(|location| location.split(':').next())
+			.and_then(|number| number.parse().ok());
+
+		diagnostics.push(Diagnostic {
+			kind: kind.to_string(),
+			line: location,
+			text: text.to_string(),
+		});
+	}
+
+	diagnostics.sort();
+	diagnostics
+}
+
+//Everything that's wrong with one program at one optimization level.
+fn check(path: &Path, level: &str, expected: &Expected) -> Vec<String> {
+	let result = Command::new(env!("CARGO_BIN_EXE_aglet"))
+		.arg("-O")
+		.arg(level)
+		.arg("--run")
+		.args(&expected.flags)
+		.arg(path)
+		.output()
+		.expect("Could not run the compiler");
+	let stdout = String::from_utf8_lossy(&result.stdout);
+	let stderr = String::from_utf8_lossy(&result.stderr);
+	let name = format!("{} (-O{})", path.display(), level);
+	let mut failures = vec![];
+
+	let should_fail = expected
+		.diagnostics
+		.iter()
+		.any(|diagnostic| diagnostic.kind == "error");
+	if result.status.success() == should_fail {
+		failures.push(format!(
+			"{}: expected {}, but it {}\n{}",
+			name,
+			if should_fail { "errors" } else { "it to run" },
+			if should_fail { "ran" } else { "failed" },
+			stderr
+		));
+	}
+
+	if stdout != expected.output {
+		failures.push(format!(
+			"{}: wrong output\n--- expected\n{}--- actual\n{}",
+			name, expected.output, stdout
+		));
+	}
+
+	let actual = diagnostics(&stderr, path);
+	for diagnostic in &expected.diagnostics {
+		if !actual.contains(diagnostic) {
+			failures.push(format!("{}: missing {:?}", name, diagnostic));
+		}
+	}
+	for diagnostic in &actual {
+		if !expected.diagnostics.contains(diagnostic) {
+			failures.push(format!("{}: unexpected {:?}", name, diagnostic));
+		}
+	}
+
+	failures
+}
+
+#[test]
+fn golden() {
+	let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+	let mut paths: Vec<_> = fs::read_dir(&directory)
+		.expect("Could not read tests/golden")
+		.map(|entry| entry.unwrap().path())
+		.filter(|path| {
+			path.extension()
+				.is_some_and(|extension| extension == "aglet")
+		})
+		.collect();
+	paths.sort();
+	assert!(!paths.is_empty(), "No golden tests found");
+
+	let mut failures = vec![];
+	for path in &paths {
+		let source = fs::read_to_string(path).unwrap();
+		let expected = expectations(&source);
+		for level in LEVELS {
+			failures.extend(check(path, level, &expected));
+		}
+	}
+
+	assert!(
+		failures.is_empty(),
+		"Golden tests failed:\n\n{}",
+		failures.join("\n")
+	);
+}
+
+//`aglet test` runs each test on its own and reports them all, failing if any of them fail.
+#[test]
+fn test_mode() {
+	let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/assert.aglet");
+	let expected = [
+		"test triples ... ok",
+		"test adds_wrong ... FAILED",
+		"test never_true ... FAILED",
+		"---- adds_wrong ----",
+		"15",
+		"---- never_true ----",
+		"test result: FAILED. 1 passed, 2 failed",
+	];
+
+	for level in LEVELS {
+		let result = Command::new(env!("CARGO_BIN_EXE_aglet"))
+			.arg("test")
+			.arg("-O")
+			.arg(level)
+			.arg(&path)
+			.output()
+			.expect("Could not run the compiler");
+		let stdout = String::from_utf8_lossy(&result.stdout);
+		let stderr = String::from_utf8_lossy(&result.stderr);
+
+		assert!(
+			!result.status.success(),
+			"-O{}: failing tests passed",
+			level
+		);
+		let lines: Vec<&str> = stdout.lines().filter(|line| !line.is_empty()).collect();
+		assert_eq!(lines, expected, "-O{}: wrong report", level);
+		assert_eq!(
+			diagnostics(&stderr, &path),
+			vec![
+				Diagnostic {
+					kind: "error".to_string(),
+					line: Some(15),
+					text: "Assertion failed: left is 4, right is 3".to_string(),
+				},
+				Diagnostic {
+					kind: "error".to_string(),
+					line: Some(19),
+					text: "Assertion failed".to_string(),
+				},
+			],
+			"-O{}: wrong failures",
+			level
+		);
+	}
+}
+
+//Build a program in `tests` into a ROM image for the given target.
+fn build(target: &str, program: &str, image: &Path) -> Output {
+	let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+	Command::new(env!("CARGO_BIN_EXE_aglet"))
+		.arg("--target")
+		.arg(target)
+		.arg("--binary")
+		.arg(directory.join(program))
+		.arg(image)
+		.output()
+		.expect("Could not run the compiler")
+}
+
+//Build `golden/print.aglet` into a ROM image for the given target.
+fn build_image(target: &str, name: &str) -> Vec<u8> {
+	let image = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+	let result = build(target, "golden/print.aglet", &image);
+	assert!(
+		result.status.success(),
+		"{}",
+		String::from_utf8_lossy(&result.stderr)
+	);
+	fs::read(image).unwrap()
+}
+
+//A target file gives a ROM image that fills the board's ROM, with the reset vector at the start.
+#[test]
+fn target_file() {
+	let target = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/targets/board.target");
+	let image = build_image(target.to_str().unwrap(), "board.bin");
+	assert_eq!(image.len(), 0x2000);
+	assert_eq!(image[0x1FFC..0x1FFE], [0x00, 0xE0]);
+}
+
+//Warnings come out in the same order every time: each kind of check in turn, and the warnings from
+//one check in the order of the code they're about.
+#[test]
+fn warning_order() {
+	let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/warnings.aglet");
+	let expected = [
+		"Unreachable code",
+		"Arithmetic overflow",
+		"Variable `ignored` is never used",
+		"Variable `steady` does not need to be mutable",
+		"Variable `spare` is never used",
+		"Function `unused` is never called",
+	];
+
+	let result = Command::new(env!("CARGO_BIN_EXE_aglet"))
+		.arg("--run")
+		.arg(&path)
+		.output()
+		.expect("Could not run the compiler");
+	let stderr = String::from_utf8_lossy(&result.stderr);
+	let warnings: Vec<&str> = stderr
+		.lines()
+		.filter_map(|line| line.strip_prefix("warning: "))
+		.collect();
+	assert_eq!(warnings.len(), expected.len(), "{}", stderr);
+	for (warning, start) in warnings.iter().zip(expected) {
+		assert!(warning.starts_with(start), "wrong order\n{}", stderr);
+	}
+}
+
+//Running out of RAM at the very top of memory is reported like anywhere else, without working out
+//addresses past $FFFF.
+#[test]
+fn target_ram_end() {
+	let target = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/targets/high.target");
+	let program = Path::new(env!("CARGO_TARGET_TMPDIR")).join("locals.aglet");
+	let image = Path::new(env!("CARGO_TARGET_TMPDIR")).join("high.bin");
+
+	//300 variables that are all needed at once.
+	let mut source = "@noinline funk locals(n: int) -> int {\n".to_string();
+	for index in 0..300 {
+		source += &format!("\tset v{} = n + {};\n", index, index);
+	}
+	let sum: Vec<String> = (0..300).rev().map(|index| format!("v{}", index)).collect();
+	source += &format!("\tret {};\n}}\n", sum.join(" + "));
+	source += "funk main() -> void {\n\tprint(locals(1));\n}\n";
+	fs::write(&program, source).unwrap();
+
+	let result = build(target.to_str().unwrap(), program.to_str().unwrap(), &image);
+	let stderr = String::from_utf8_lossy(&result.stderr);
+	assert!(!result.status.success(), "built anyway");
+	assert!(
+		stderr.contains("error: Not enough RAM: variables need 356 bytes from $FF00, but there are only 240 bytes up to $FFEF"),
+		"wrong errors\n{}",
+		stderr
+	);
+	assert!(!stderr.contains("panicked"), "{}", stderr);
+}
+
+//Only `test` itself is the `aglet test` subcommand: files named anything like it are just files.
+#[test]
+fn test_lookalikes() {
+	let directory = Path::new(env!("CARGO_TARGET_TMPDIR"));
+	let source = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/print.aglet");
+	for name in ["tes", "testing.aglet", "tests.aglet"] {
+		fs::copy(&source, directory.join(name)).unwrap();
+		let output = format!("{}.s", name);
+		let result = Command::new(env!("CARGO_BIN_EXE_aglet"))
+			.current_dir(directory)
+			.arg(name)
+			.arg(&output)
+			.output()
+			.expect("Could not run the compiler");
+		assert!(
+			result.status.success(),
+			"{}: {}",
+			name,
+			String::from_utf8_lossy(&result.stderr)
+		);
+		assert!(directory.join(&output).exists(), "{}: no output", name);
+	}
+}
+
+//Run a program in `tests` on the emulator, standing in for the given target's computer.
+//Its path is relative, starting with `tests/`, which mustn't be mistaken for `aglet test`.
+fn run(target: &str, program: &str, flags: &[&str]) -> Output {
+	Command::new(env!("CARGO_BIN_EXE_aglet"))
+		.current_dir(env!("CARGO_MANIFEST_DIR"))
+		.arg("--target")
+		.arg(target)
+		.arg("--run")
+		.args(flags)
+		.arg(Path::new("tests").join(program))
+		.output()
+		.expect("Could not run the compiler")
+}
+
+//What the `//out:` lines of a program in `tests` say it prints.
+fn expected_output(program: &str) -> String {
+	let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+		.join("tests")
+		.join(program);
+	expectations(&fs::read_to_string(path).unwrap()).output
+}
+
+//The breadboard computer gets a whole 32K EEPROM image.
+#[test]
+fn breadboard_target() {
+	let image = build_image("breadboard", "breadboard.bin");
+	assert_eq!(image.len(), 0x8000);
+	assert_eq!(image[0x7FFC..0x7FFE], [0x00, 0x80]);
+}
+
+//On the breadboard computer, each number goes on a line of the LCD, and so does a failure.
+#[test]
+fn breadboard_lcd() {
+	for (program, expected) in [
+		("golden/print.aglet", expected_output("golden/print.aglet")),
+		("golden/stack.aglet", "100\nOut of stack".to_string()),
+	] {
+		let result = run("breadboard", program, &[]);
+		assert!(
+			result.status.success(),
+			"{}: {}",
+			program,
+			String::from_utf8_lossy(&result.stderr)
+		);
+		assert_eq!(
+			String::from_utf8_lossy(&result.stdout)
+				.lines()
+				.collect::<Vec<_>>(),
+			expected.lines().collect::<Vec<_>>(),
+			"{}: wrong LCD contents",
+			program
+		);
+	}
+}
+
+//A C64 program is a PRG file: the load address, then a BASIC program that calls the real one.
+#[test]
+fn c64_target() {
+	let image = build_image("c64", "c64.prg");
+	assert_eq!(
+		image[..14],
+		[0x01, 0x08, 0x0B, 0x08, 0x0A, 0x00, 0x9E, b'2', b'0', b'6', b'1', 0x00, 0x00, 0x00]
+	);
+}
+
+//On the C64, everything is printed through the KERNAL's `CHROUT`, failures included.
+//BASIC and the KERNAL leave so little zero page that the workspace for recursion's arithmetic
+//doesn't fit, so it's allowed to go in regular memory.
+#[test]
+fn c64_chrout() {
+	for (program, expected) in [
+		("golden/print.aglet", expected_output("golden/print.aglet")),
+		("golden/stack.aglet", "100\nOUT OF STACK\n".to_string()),
+	] {
+		let result = run("c64", program, &["--zp-spill"]);
+		assert!(
+			result.status.success(),
+			"{}: {}",
+			program,
+			String::from_utf8_lossy(&result.stderr)
+		);
+		assert_eq!(
+			String::from_utf8_lossy(&result.stdout),
+			expected,
+			"{}: wrong output",
+			program
+		);
+	}
+}
+
+//Variables that don't fit in the target's RAM are an error, not a program that overwrites itself.
+#[test]
+fn target_ram() {
+	let target = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/targets/tiny.target");
+	let image = Path::new(env!("CARGO_TARGET_TMPDIR")).join("tiny.bin");
+	for (program, error) in [
+		(
+			"golden/functions.aglet",
+			"Not enough RAM: variables need 21 bytes from $0200, but there are only 16 bytes up to $020F",
+		),
+		(
+			"golden/recursion.aglet",
+			"Not enough RAM: variables need 15 bytes from $0200, along with at least 4 bytes of software stack for recursive functions, but there are only 16 bytes up to $020F",
+		),
+	] {
+		let result = build(target.to_str().unwrap(), program, &image);
+		assert!(!result.status.success(), "{}: built anyway", program);
+		let stderr = String::from_utf8_lossy(&result.stderr);
+		assert!(
+			stderr.contains(&format!("error: {}", error)),
+			"{}: wrong errors\n{}",
+			program,
+			stderr
+		);
+	}
+}